@@ -0,0 +1,567 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// result of ticking a node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    /// the node needs more ticks to finish
+    Running,
+}
+
+/// a behaviour tree as written in the data file. Composites are reactive, they start over at
+/// their first child every tick, so conditions in front of a running action are checked again.
+#[derive(Deserialize, Clone, Debug)]
+pub enum BehaviourNode {
+    /// run the children in order until one does not succeed
+    Sequence(Vec<BehaviourNode>),
+    /// run the children in order until one does not fail
+    Selector(Vec<BehaviourNode>),
+    /// run all children every tick, succeed once `success` of them succeeded and fail once that
+    /// is no longer possible
+    Parallel {
+        success: usize,
+        children: Vec<BehaviourNode>,
+    },
+    /// swap success and failure of the child
+    Invert(Box<BehaviourNode>),
+    /// succeed even if the child fails
+    AlwaysSucceed(Box<BehaviourNode>),
+    /// run the child until it succeeded `count` times, 0 repeats forever
+    Repeat {
+        count: u32,
+        child: Box<BehaviourNode>,
+    },
+    /// fail for `seconds` after the child finished
+    Cooldown {
+        seconds: f32,
+        child: Box<BehaviourNode>,
+    },
+    /// leaf answering a question, see [`Leaves::condition`]
+    Condition(String),
+    /// leaf doing something, see [`Leaves::action`]
+    Action(String),
+}
+
+/// what a leaf gets to work with: the whole world and the entity the tree runs for
+pub struct LeafContext<'a> {
+    pub world: &'a mut World,
+    pub entity: Entity,
+    /// seconds since the last tick
+    pub delta: f32,
+}
+
+type ActionFn = Box<dyn Fn(&mut LeafContext) -> Status + Send + Sync>;
+type ConditionFn = Box<dyn Fn(&mut LeafContext) -> bool + Send + Sync>;
+
+enum Leaf {
+    Action(ActionFn),
+    Condition(ConditionFn),
+}
+
+/// named leaves the trees in the data file can refer to
+#[derive(Default)]
+pub struct Leaves {
+    names: HashMap<String, usize>,
+    leaves: Vec<Leaf>,
+}
+
+impl Leaves {
+    pub fn action(
+        self,
+        name: &str,
+        action: impl Fn(&mut LeafContext) -> Status + Send + Sync + 'static,
+    ) -> Self {
+        self.with_leaf(name, Leaf::Action(Box::new(action)))
+    }
+
+    pub fn condition(
+        self,
+        name: &str,
+        condition: impl Fn(&mut LeafContext) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.with_leaf(name, Leaf::Condition(Box::new(condition)))
+    }
+
+    fn with_leaf(mut self, name: &str, leaf: Leaf) -> Self {
+        if self
+            .names
+            .insert(name.to_string(), self.leaves.len())
+            .is_some()
+        {
+            panic!("behaviour tree leaf {name} registered twice");
+        }
+        self.leaves.push(leaf);
+        self
+    }
+}
+
+/// a tree with its leaves resolved, nodes refer to their children by index
+pub struct BehaviourTree {
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+enum Node {
+    Sequence(Vec<usize>),
+    Selector(Vec<usize>),
+    Parallel {
+        success: usize,
+        children: Vec<usize>,
+    },
+    Invert(usize),
+    AlwaysSucceed(usize),
+    Repeat {
+        count: u32,
+        child: usize,
+    },
+    Cooldown {
+        seconds: f32,
+        child: usize,
+    },
+    Leaf(usize),
+}
+
+/// per agent memory of a tree, start with the default
+#[derive(Clone, Debug, Default)]
+pub struct TreeState {
+    /// seconds the tree has been ticked for
+    elapsed: f32,
+    memory: Vec<NodeMemory>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct NodeMemory {
+    /// successful runs of the child of a repeat node
+    count: u32,
+    /// `elapsed` at which a cooldown node is ready again
+    ready_at: f32,
+}
+
+impl BehaviourTree {
+    /// resolve the leaves of a tree, panics on leaves that are not registered or are used as the
+    /// wrong kind
+    pub fn build(root: &BehaviourNode, leaves: &Leaves) -> Self {
+        let mut tree = Self {
+            nodes: Vec::new(),
+            root: 0,
+        };
+        tree.root = tree.add(root, leaves);
+        tree
+    }
+
+    fn add(&mut self, node: &BehaviourNode, leaves: &Leaves) -> usize {
+        let mut add_all = |children: &[BehaviourNode]| -> Vec<usize> {
+            children
+                .iter()
+                .map(|child| self.add(child, leaves))
+                .collect()
+        };
+        let node = match node {
+            BehaviourNode::Sequence(children) => Node::Sequence(add_all(children)),
+            BehaviourNode::Selector(children) => Node::Selector(add_all(children)),
+            BehaviourNode::Parallel { success, children } => Node::Parallel {
+                success: *success,
+                children: add_all(children),
+            },
+            BehaviourNode::Invert(child) => Node::Invert(self.add(child, leaves)),
+            BehaviourNode::AlwaysSucceed(child) => Node::AlwaysSucceed(self.add(child, leaves)),
+            BehaviourNode::Repeat { count, child } => Node::Repeat {
+                count: *count,
+                child: self.add(child, leaves),
+            },
+            BehaviourNode::Cooldown { seconds, child } => Node::Cooldown {
+                seconds: *seconds,
+                child: self.add(child, leaves),
+            },
+            BehaviourNode::Condition(name) | BehaviourNode::Action(name) => {
+                let &index = leaves
+                    .names
+                    .get(name)
+                    .unwrap_or_else(|| panic!("unknown behaviour tree leaf {name}"));
+                let is_condition = matches!(node, BehaviourNode::Condition(_));
+                if is_condition != matches!(leaves.leaves[index], Leaf::Condition(_)) {
+                    panic!("behaviour tree leaf {name} used as the wrong kind of leaf");
+                }
+                Node::Leaf(index)
+            }
+        };
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// run the tree once for the entity in the context
+    pub fn tick(
+        &self,
+        state: &mut TreeState,
+        leaves: &Leaves,
+        context: &mut LeafContext,
+    ) -> Status {
+        state.memory.resize(self.nodes.len(), NodeMemory::default());
+        state.elapsed += context.delta;
+        self.tick_node(self.root, state, leaves, context)
+    }
+
+    fn tick_node(
+        &self,
+        index: usize,
+        state: &mut TreeState,
+        leaves: &Leaves,
+        context: &mut LeafContext,
+    ) -> Status {
+        match &self.nodes[index] {
+            Node::Sequence(children) => {
+                for &child in children {
+                    match self.tick_node(child, state, leaves, context) {
+                        Status::Success => continue,
+                        status => return status,
+                    }
+                }
+                Status::Success
+            }
+            Node::Selector(children) => {
+                for &child in children {
+                    match self.tick_node(child, state, leaves, context) {
+                        Status::Failure => continue,
+                        status => return status,
+                    }
+                }
+                Status::Failure
+            }
+            Node::Parallel { success, children } => {
+                let (mut succeeded, mut failed) = (0, 0);
+                for &child in children {
+                    match self.tick_node(child, state, leaves, context) {
+                        Status::Success => succeeded += 1,
+                        Status::Failure => failed += 1,
+                        Status::Running => {}
+                    }
+                }
+                if succeeded >= *success {
+                    Status::Success
+                } else if failed > children.len().saturating_sub(*success) {
+                    Status::Failure
+                } else {
+                    Status::Running
+                }
+            }
+            Node::Invert(child) => match self.tick_node(*child, state, leaves, context) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            Node::AlwaysSucceed(child) => match self.tick_node(*child, state, leaves, context) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            Node::Repeat { count, child } => match self.tick_node(*child, state, leaves, context) {
+                Status::Success => {
+                    let memory = &mut state.memory[index];
+                    memory.count += 1;
+                    if *count != 0 && memory.count >= *count {
+                        memory.count = 0;
+                        Status::Success
+                    } else {
+                        Status::Running
+                    }
+                }
+                Status::Failure => {
+                    state.memory[index].count = 0;
+                    Status::Failure
+                }
+                Status::Running => Status::Running,
+            },
+            Node::Cooldown { seconds, child } => {
+                if state.elapsed < state.memory[index].ready_at {
+                    return Status::Failure;
+                }
+                let status = self.tick_node(*child, state, leaves, context);
+                if status != Status::Running {
+                    state.memory[index].ready_at = state.elapsed + seconds;
+                }
+                status
+            }
+            Node::Leaf(leaf) => match &leaves.leaves[*leaf] {
+                Leaf::Action(action) => action(context),
+                Leaf::Condition(condition) => {
+                    if condition(context) {
+                        Status::Success
+                    } else {
+                        Status::Failure
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// all behaviour trees of the game by name, together with the leaves they use
+#[derive(Resource)]
+pub struct BehaviourTrees {
+    leaves: Leaves,
+    trees: HashMap<String, BehaviourTree>,
+}
+
+impl BehaviourTrees {
+    /// read a ron file with trees by name and resolve their leaves, panics like the ship
+    /// definitions do
+    pub fn load(path: &str, leaves: Leaves) -> Self {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("could not read behaviour trees {path}: {err}"));
+        let definitions: HashMap<String, BehaviourNode> = ron::from_str(&source)
+            .unwrap_or_else(|err| panic!("could not parse behaviour trees {path}: {err}"));
+        let trees = definitions
+            .iter()
+            .map(|(name, root)| (name.clone(), BehaviourTree::build(root, &leaves)))
+            .collect();
+        Self { leaves, trees }
+    }
+
+    /// run the named tree once, `None` if there is no such tree
+    pub fn tick(
+        &self,
+        name: &str,
+        state: &mut TreeState,
+        context: &mut LeafContext,
+    ) -> Option<Status> {
+        let tree = self.trees.get(name)?;
+        Some(tree.tick(state, &self.leaves, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// names of the leaves an entity ran, in order
+    #[derive(Component, Default)]
+    struct Calls(Vec<&'static str>);
+
+    fn record(context: &mut LeafContext, name: &'static str) {
+        if let Some(mut calls) = context.world.get_mut::<Calls>(context.entity) {
+            calls.0.push(name);
+        }
+    }
+
+    fn leaves() -> Leaves {
+        let recorded = |leaves: Leaves, name: &'static str, status| {
+            leaves.action(name, move |context| {
+                record(context, name);
+                status
+            })
+        };
+        let leaves = Leaves::default()
+            .condition("yes", |context| {
+                record(context, "yes");
+                true
+            })
+            .condition("no", |context| {
+                record(context, "no");
+                false
+            });
+        let leaves = recorded(leaves, "succeed", Status::Success);
+        let leaves = recorded(leaves, "fail", Status::Failure);
+        recorded(leaves, "running", Status::Running)
+    }
+
+    fn action(name: &str) -> BehaviourNode {
+        BehaviourNode::Action(name.to_string())
+    }
+
+    /// a tree on an entity of a bare world, ticked a given number of seconds apart
+    struct Harness {
+        world: World,
+        entity: Entity,
+        leaves: Leaves,
+        tree: BehaviourTree,
+        state: TreeState,
+    }
+
+    impl Harness {
+        fn new(root: BehaviourNode) -> Self {
+            let mut world = World::new();
+            let entity = world.spawn(Calls::default()).id();
+            let leaves = leaves();
+            let tree = BehaviourTree::build(&root, &leaves);
+            Self {
+                world,
+                entity,
+                leaves,
+                tree,
+                state: TreeState::default(),
+            }
+        }
+
+        fn tick(&mut self, delta: f32) -> Status {
+            let mut context = LeafContext {
+                world: &mut self.world,
+                entity: self.entity,
+                delta,
+            };
+            self.tree.tick(&mut self.state, &self.leaves, &mut context)
+        }
+
+        /// leaves run since the last call
+        fn calls(&mut self) -> Vec<&'static str> {
+            std::mem::take(&mut self.world.get_mut::<Calls>(self.entity).unwrap().0)
+        }
+    }
+
+    #[test]
+    fn sequence_stops_at_the_first_child_that_does_not_succeed() {
+        let mut tree = Harness::new(BehaviourNode::Sequence(vec![
+            action("succeed"),
+            action("fail"),
+            action("succeed"),
+        ]));
+        assert_eq!(tree.tick(0.1), Status::Failure);
+        assert_eq!(tree.calls(), ["succeed", "fail"]);
+
+        let mut tree = Harness::new(BehaviourNode::Sequence(vec![
+            BehaviourNode::Condition("yes".to_string()),
+            action("running"),
+            action("fail"),
+        ]));
+        assert_eq!(tree.tick(0.1), Status::Running);
+        assert_eq!(tree.calls(), ["yes", "running"]);
+    }
+
+    #[test]
+    fn selector_stops_at_the_first_child_that_does_not_fail() {
+        let mut tree = Harness::new(BehaviourNode::Selector(vec![
+            BehaviourNode::Condition("no".to_string()),
+            action("succeed"),
+            action("fail"),
+        ]));
+        assert_eq!(tree.tick(0.1), Status::Success);
+        assert_eq!(tree.calls(), ["no", "succeed"]);
+
+        let mut tree = Harness::new(BehaviourNode::Selector(vec![
+            action("fail"),
+            action("running"),
+            action("succeed"),
+        ]));
+        assert_eq!(tree.tick(0.1), Status::Running);
+        assert_eq!(tree.calls(), ["fail", "running"]);
+    }
+
+    #[test]
+    fn parallel_ticks_every_child_and_applies_its_policy() {
+        let parallel = |children: [&str; 3]| {
+            Harness::new(BehaviourNode::Parallel {
+                success: 2,
+                children: children.into_iter().map(action).collect(),
+            })
+        };
+
+        let mut tree = parallel(["succeed", "running", "succeed"]);
+        assert_eq!(tree.tick(0.1), Status::Success);
+        assert_eq!(tree.calls(), ["succeed", "running", "succeed"]);
+
+        // one failure still leaves room for two successes
+        let mut tree = parallel(["succeed", "running", "fail"]);
+        assert_eq!(tree.tick(0.1), Status::Running);
+
+        let mut tree = parallel(["fail", "running", "fail"]);
+        assert_eq!(tree.tick(0.1), Status::Failure);
+        assert_eq!(tree.calls(), ["fail", "running", "fail"]);
+    }
+
+    #[test]
+    fn invert_and_always_succeed_pass_running_through() {
+        let invert = |name| Harness::new(BehaviourNode::Invert(Box::new(action(name))));
+        assert_eq!(invert("succeed").tick(0.1), Status::Failure);
+        assert_eq!(invert("fail").tick(0.1), Status::Success);
+        assert_eq!(invert("running").tick(0.1), Status::Running);
+
+        let always = |name| Harness::new(BehaviourNode::AlwaysSucceed(Box::new(action(name))));
+        assert_eq!(always("succeed").tick(0.1), Status::Success);
+        assert_eq!(always("fail").tick(0.1), Status::Success);
+        assert_eq!(always("running").tick(0.1), Status::Running);
+    }
+
+    #[test]
+    fn repeat_succeeds_after_count_successes_and_starts_over() {
+        let mut tree = Harness::new(BehaviourNode::Repeat {
+            count: 3,
+            child: Box::new(action("succeed")),
+        });
+        let statuses: Vec<Status> = (0..4).map(|_| tree.tick(0.1)).collect();
+        assert_eq!(
+            statuses,
+            [
+                Status::Running,
+                Status::Running,
+                Status::Success,
+                Status::Running
+            ]
+        );
+
+        let mut tree = Harness::new(BehaviourNode::Repeat {
+            count: 0,
+            child: Box::new(action("fail")),
+        });
+        assert_eq!(tree.tick(0.1), Status::Failure);
+    }
+
+    #[test]
+    fn cooldown_fails_without_ticking_its_child_until_it_is_over() {
+        let mut tree = Harness::new(BehaviourNode::Cooldown {
+            seconds: 1.0,
+            child: Box::new(action("succeed")),
+        });
+        assert_eq!(tree.tick(0.4), Status::Success);
+        assert_eq!(tree.calls(), ["succeed"]);
+
+        assert_eq!(tree.tick(0.4), Status::Failure);
+        assert_eq!(tree.tick(0.4), Status::Failure);
+        assert!(tree.calls().is_empty());
+
+        assert_eq!(tree.tick(0.4), Status::Success);
+        assert_eq!(tree.calls(), ["succeed"]);
+    }
+
+    #[test]
+    fn cooldown_starts_once_the_child_finished() {
+        let mut tree = Harness::new(BehaviourNode::Cooldown {
+            seconds: 1.0,
+            child: Box::new(action("running")),
+        });
+        assert_eq!(tree.tick(0.4), Status::Running);
+        assert_eq!(tree.tick(0.4), Status::Running);
+        assert_eq!(tree.calls(), ["running", "running"]);
+    }
+
+    #[test]
+    fn trees_load_from_ron() {
+        let definitions: HashMap<String, BehaviourNode> = ron::from_str(
+            r#"{
+                "guard": Selector([
+                    Sequence([Condition("no"), Action("fail")]),
+                    Repeat(count: 2, child: Action("succeed")),
+                ]),
+            }"#,
+        )
+        .unwrap();
+        let mut tree = Harness::new(definitions["guard"].clone());
+
+        assert_eq!(tree.tick(0.1), Status::Running);
+        assert_eq!(tree.tick(0.1), Status::Success);
+        assert_eq!(tree.calls(), ["no", "succeed", "no", "succeed"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown behaviour tree leaf")]
+    fn unknown_leaves_are_rejected() {
+        Harness::new(action("dance"));
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong kind of leaf")]
+    fn conditions_can_not_be_used_as_actions() {
+        Harness::new(action("yes"));
+    }
+}
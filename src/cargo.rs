@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::level::{AreaDefinition, LevelDefinition};
+use crate::ship::ShipInput;
+use crate::state::GameplaySet;
+use crate::PIXELS_PER_METER;
+
+/// distance in pixels from the ship within which the tractor beam grabs a pod
+const TRACTOR_RANGE: f32 = 60.0;
+
+/// length of the tether rope in pixels
+const TETHER_LENGTH: f32 = 90.0;
+
+/// tension in newtons above which the tether snaps
+const TETHER_BREAK_FORCE: f32 = 6000.0;
+
+/// share of the newest tension sample in the smoothed stress, so single contact spikes do not
+/// snap the rope right away
+const STRESS_SMOOTHING: f32 = 0.2;
+
+/// radius of a cargo pod in pixels
+const POD_RADIUS: f32 = 8.0;
+
+pub struct CargoPlugin;
+impl Plugin for CargoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CargoDelivered>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    tractor_beam_system,
+                    tether_stress_system,
+                    cargo_delivery_system,
+                )
+                    .chain()
+                    .after(PhysicsSet::Writeback)
+                    .in_set(GameplaySet),
+            )
+            .add_systems(Update, tether_line_system.in_set(GameplaySet));
+    }
+}
+
+/// a pod waiting in the level to be towed to the goal
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct CargoDefinition {
+    pub position: (f32, f32),
+    /// kilograms
+    #[serde(default = "default_pod_mass")]
+    pub mass: f32,
+}
+
+fn default_pod_mass() -> f32 {
+    300.0
+}
+
+/// a pod that can be picked up with the tractor beam
+#[derive(Component)]
+pub struct CargoPod {
+    mass: f32,
+}
+
+/// area pods have to be brought to
+#[derive(Component)]
+pub struct CargoGoal {
+    radius: f32,
+}
+
+/// rope from a ship to the pod it tows, lives on the pod next to the rope joint
+#[derive(Component)]
+pub struct Tether {
+    pub ship: Entity,
+    /// smoothed rope tension in newtons
+    pub stress: f32,
+    /// pod velocity of the last tick, for the acceleration
+    last_velocity: Vec2,
+    /// sprite drawing the rope
+    line: Entity,
+}
+
+/// a pod reached the goal and is gone
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CargoDelivered;
+
+pub fn spawn_cargo_pod(commands: &mut Commands, definition: &CargoDefinition) -> Entity {
+    let (x, y) = definition.position;
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.9, 0.7, 0.2),
+                    custom_size: Some(Vec2::splat(POD_RADIUS * 2.0)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, 0.0),
+                ..default()
+            },
+            CargoPod {
+                mass: definition.mass,
+            },
+            RigidBody::Dynamic,
+            Collider::ball(POD_RADIUS),
+            ColliderMassProperties::Mass(definition.mass),
+            Damping {
+                linear_damping: 0.3,
+                angular_damping: 1.0,
+            },
+            Velocity::default(),
+        ))
+        .id()
+}
+
+pub fn spawn_cargo_goal(commands: &mut Commands, area: &AreaDefinition) -> Entity {
+    let (x, y) = area.position;
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.9, 0.7, 0.2, 0.25),
+                    custom_size: Some(Vec2::splat(area.radius * 2.0)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, -0.1),
+                ..default()
+            },
+            CargoGoal {
+                radius: area.radius,
+            },
+        ))
+        .id()
+}
+
+/// place the pods and the goal of the level
+pub(crate) fn spawn_level_cargo(commands: &mut Commands, level: &LevelDefinition) {
+    for pod in &level.cargo {
+        spawn_cargo_pod(commands, pod);
+    }
+    if let Some(goal) = &level.cargo_goal {
+        spawn_cargo_goal(commands, goal);
+    }
+}
+
+/// pressing the tractor button grabs the closest free pod in range, or lets go of the towed one
+fn tractor_beam_system(
+    mut commands: Commands,
+    ships: Query<(Entity, &ShipInput, &Transform)>,
+    pods: Query<(Entity, &Transform, &Velocity, Option<&Tether>), With<CargoPod>>,
+    mut held: Local<HashSet<Entity>>,
+) {
+    // forget destroyed ships, and the ships of earlier runs
+    held.retain(|&ship| ships.contains(ship));
+    for (ship, input, ship_transform) in &ships {
+        // only the moment the button goes down counts
+        let pressed = input.tractor && !held.contains(&ship);
+        if input.tractor {
+            held.insert(ship);
+        } else {
+            held.remove(&ship);
+        }
+        if !pressed {
+            continue;
+        }
+
+        if let Some((pod, _, _, tether)) = pods
+            .iter()
+            .find(|(_, _, _, tether)| tether.is_some_and(|tether| tether.ship == ship))
+        {
+            release(&mut commands, pod, tether.unwrap());
+            continue;
+        }
+
+        let ship_position = ship_transform.translation.xy();
+        let closest = pods
+            .iter()
+            .filter(|(_, _, _, tether)| tether.is_none())
+            .map(|(pod, transform, velocity, _)| {
+                let distance = transform.translation.xy().distance(ship_position);
+                (pod, distance, velocity.linvel)
+            })
+            .filter(|(_, distance, _)| *distance <= TRACTOR_RANGE)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((pod, _, velocity)) = closest else {
+            continue;
+        };
+
+        let line = commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.6, 0.9, 1.0, 0.8),
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
+        // the rope only limits the distance, it goes slack when the ship comes closer
+        commands.entity(pod).insert((
+            ImpulseJoint::new(ship, RopeJointBuilder::new(TETHER_LENGTH)),
+            Tether {
+                ship,
+                stress: 0.0,
+                last_velocity: velocity,
+                line,
+            },
+        ));
+    }
+}
+
+fn release(commands: &mut Commands, pod: Entity, tether: &Tether) {
+    commands.entity(pod).remove::<(ImpulseJoint, Tether)>();
+    commands.entity(tether.line).despawn();
+}
+
+/// estimate the rope tension from the acceleration of the pod and snap ropes under too much
+/// stress. Gravity pulls on the pod all the time, only the acceleration beyond it is the rope.
+fn tether_stress_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<RapierConfiguration>,
+    ships: Query<&Transform, With<ShipInput>>,
+    mut pods: Query<(Entity, &CargoPod, &Transform, &Velocity, &mut Tether)>,
+) {
+    let delta = time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+    for (pod, cargo, transform, velocity, mut tether) in &mut pods {
+        let Ok(ship_transform) = ships.get(tether.ship) else {
+            // the ship is gone, the rope goes with it
+            release(&mut commands, pod, &tether);
+            continue;
+        };
+        let acceleration = (velocity.linvel - tether.last_velocity) / delta - config.gravity;
+        tether.last_velocity = velocity.linvel;
+
+        let rope = ship_transform.translation.xy() - transform.translation.xy();
+        let taut = rope.length() >= TETHER_LENGTH * 0.98;
+        // bevy_rapier works in pixels, newtons are kilograms times meters per second squared
+        let tension = if taut {
+            (acceleration.dot(rope.normalize_or_zero()) * cargo.mass / PIXELS_PER_METER).max(0.0)
+        } else {
+            0.0
+        };
+        tether.stress += (tension - tether.stress) * STRESS_SMOOTHING;
+
+        if tether.stress > TETHER_BREAK_FORCE {
+            info!("tether snapped at {:.0} N", tether.stress);
+            release(&mut commands, pod, &tether);
+        }
+    }
+}
+
+/// pods inside the goal are delivered
+pub(crate) fn cargo_delivery_system(
+    mut commands: Commands,
+    goals: Query<(&CargoGoal, &Transform)>,
+    pods: Query<(Entity, &Transform, Option<&Tether>), With<CargoPod>>,
+    mut delivered: EventWriter<CargoDelivered>,
+) {
+    for (pod, transform, tether) in &pods {
+        let position = transform.translation.xy();
+        let in_goal = goals.iter().any(|(goal, goal_transform)| {
+            goal_transform.translation.xy().distance(position) <= goal.radius
+        });
+        if !in_goal {
+            continue;
+        }
+        if let Some(tether) = tether {
+            commands.entity(tether.line).despawn();
+        }
+        commands.entity(pod).despawn_recursive();
+        delivered.send(CargoDelivered);
+    }
+}
+
+/// stretch the rope sprites between ship and pod
+fn tether_line_system(
+    pods: Query<(&Transform, &Tether)>,
+    ships: Query<&Transform, With<ShipInput>>,
+    mut lines: Query<(&mut Transform, &mut Sprite), (Without<Tether>, Without<ShipInput>)>,
+) {
+    for (pod_transform, tether) in &pods {
+        let (Ok(ship_transform), Ok((mut transform, mut sprite))) =
+            (ships.get(tether.ship), lines.get_mut(tether.line))
+        else {
+            continue;
+        };
+        let from = ship_transform.translation.xy();
+        let to = pod_transform.translation.xy();
+        let rope = to - from;
+        transform.translation = ((from + to) / 2.0).extend(-0.05);
+        transform.rotation = Quat::from_rotation_z(rope.y.atan2(rope.x));
+        sprite.custom_size = Some(Vec2::new(rope.length(), 1.5));
+    }
+}
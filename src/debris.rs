@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use image::Rgba;
+use rand::Rng;
+
+use crate::level::{carve_terrain_system, LevelMap, TerrainCarved, TerrainChanged};
+use crate::particles::{ParticleEffect, ParticlePreset};
+use crate::replay::GameRng;
+use crate::state::GameplaySet;
+
+pub struct DebrisPlugin;
+impl Plugin for DebrisPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebrisSettings>().add_systems(
+            FixedUpdate,
+            (spawn_debris_system, settle_debris_system)
+                .chain()
+                // baking debris rebuilds terrain rows, see the carving system
+                .after(PhysicsSet::Writeback)
+                // carved pixels are turned into debris in the same tick, events only live for
+                // two frames and there may be no tick in between
+                .after(carve_terrain_system)
+                .in_set(GameplaySet),
+        );
+    }
+}
+
+/// tuning of the debris created when terrain is carved away
+#[derive(Resource)]
+pub struct DebrisSettings {
+    /// share of the removed pixels that turn into debris bodies, 0.0 to 1.0
+    pub fraction: f32,
+    /// upper bound of live debris bodies
+    pub max_bodies: usize,
+    /// initial speed range away from the carve center in pixels per second
+    pub speed: (f32, f32),
+    /// bodies slower than this (pixels per second) count as resting
+    pub rest_speed: f32,
+    /// seconds a body has to rest before it is settled
+    pub rest_time: f32,
+    /// seconds after which a body is removed even if it never came to rest
+    pub max_lifetime: f32,
+    /// write settled debris back into the terrain bitmap instead of just removing it
+    pub bake_into_terrain: bool,
+}
+
+impl Default for DebrisSettings {
+    fn default() -> Self {
+        Self {
+            fraction: 0.2,
+            max_bodies: 400,
+            speed: (20.0, 120.0),
+            rest_speed: 2.0,
+            rest_time: 0.5,
+            max_lifetime: 10.0,
+            bake_into_terrain: true,
+        }
+    }
+}
+
+/// a single terrain pixel that broke loose
+#[derive(Component)]
+pub(crate) struct Debris {
+    /// level colour of the pixel, used when baking it back into the terrain
+    color: Rgba<u8>,
+    age: f32,
+    resting: f32,
+}
+
+/// turn part of the carved pixels into small dynamic bodies flying away from the impact
+fn spawn_debris_system(
+    mut commands: Commands,
+    settings: Res<DebrisSettings>,
+    mut carved: EventReader<TerrainCarved>,
+    mut effects: EventWriter<ParticleEffect>,
+    debris_query: Query<(), With<Debris>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let mut live = debris_query.iter().count();
+
+    for event in carved.read() {
+        // cheap dust on top of the physical debris
+        effects.send(ParticleEffect {
+            preset: ParticlePreset::TerrainDebris,
+            position: event.center,
+            direction: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+        });
+
+        for &(pixel, color) in &event.pixels {
+            if live >= settings.max_bodies {
+                break;
+            }
+            if !rng.gen_bool(settings.fraction.clamp(0.0, 1.0) as f64) {
+                continue;
+            }
+            live += 1;
+
+            let position = pixel.as_vec2() + 0.5;
+            let away = (position - event.center).try_normalize().unwrap_or(Vec2::Y);
+            let speed = rng.gen_range(settings.speed.0..=settings.speed.1);
+
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb_u8(color.0[0], color.0[1], color.0[2]),
+                        custom_size: Some(Vec2::ONE),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(position.extend(0.0)),
+                    ..default()
+                },
+                Debris {
+                    color,
+                    age: 0.0,
+                    resting: 0.0,
+                },
+                RigidBody::Dynamic,
+                Collider::cuboid(0.5, 0.5),
+                Velocity::linear(away * speed),
+                Restitution::coefficient(0.2),
+                // the bodies are a single pixel, without ccd they fall through thin terrain
+                Ccd::enabled(),
+            ));
+        }
+    }
+}
+
+/// remove debris once it came to rest or is too old, optionally baking it into the terrain
+pub(crate) fn settle_debris_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<DebrisSettings>,
+    mut level: Option<ResMut<LevelMap>>,
+    mut changed: EventWriter<TerrainChanged>,
+    mut query: Query<(Entity, &mut Debris, &Transform, &Velocity)>,
+) {
+    let delta = time.delta_seconds();
+    let mut dirty_rows: Vec<i32> = Vec::new();
+    let mut bounds: Option<(IVec2, IVec2)> = None;
+
+    for (entity, mut debris, transform, velocity) in &mut query {
+        debris.age += delta;
+        if velocity.linvel.length() < settings.rest_speed {
+            debris.resting += delta;
+        } else {
+            debris.resting = 0.0;
+        }
+
+        let settled = debris.resting >= settings.rest_time;
+        if !settled && debris.age < settings.max_lifetime {
+            continue;
+        }
+        commands.entity(entity).despawn();
+
+        let Some(level) = level.as_deref_mut() else {
+            continue;
+        };
+        if !settled || !settings.bake_into_terrain {
+            continue;
+        }
+        let pixel = transform.translation.xy().floor().as_ivec2();
+        if level.is_solid_pixel(pixel.x, pixel.y)
+            || !level.set_pixel(pixel.x, pixel.y, debris.color)
+        {
+            continue;
+        }
+        if !dirty_rows.contains(&pixel.y) {
+            dirty_rows.push(pixel.y);
+        }
+        bounds = Some(match bounds {
+            Some((min, max)) => (min.min(pixel), max.max(pixel)),
+            None => (pixel, pixel),
+        });
+    }
+
+    if let (Some(level), Some((min, max))) = (level.as_deref_mut(), bounds) {
+        for y in dirty_rows {
+            level.rebuild_row(&mut commands, y as u32);
+        }
+        changed.send(TerrainChanged { min, max });
+    }
+}
@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// selected difficulty, enemies read their tuning from it
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// enemy tuning for one difficulty level
+#[derive(Clone, Copy, Debug)]
+pub struct DifficultySettings {
+    /// largest random error in radians added to every enemy shot
+    pub aim_error: f32,
+    /// share of the predicted target movement enemies lead their shots by, 1.0 is perfect lead
+    pub lead_factor: f32,
+    /// seconds an enemy has to see the player in attack range before it opens fire
+    pub reaction_time: f32,
+}
+
+impl Difficulty {
+    pub fn settings(self) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                aim_error: 0.2,
+                lead_factor: 0.0,
+                reaction_time: 1.0,
+            },
+            Difficulty::Normal => DifficultySettings {
+                aim_error: 0.08,
+                lead_factor: 0.7,
+                reaction_time: 0.5,
+            },
+            Difficulty::Hard => DifficultySettings {
+                aim_error: 0.02,
+                lead_factor: 1.0,
+                reaction_time: 0.2,
+            },
+        }
+    }
+}
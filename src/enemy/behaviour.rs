@@ -0,0 +1,181 @@
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use rand::Rng;
+
+use super::brain::Perception;
+use super::pattern::{FirePatterns, PatternEmitter};
+use super::turret::Turret;
+use super::{rotate_towards, snap_towards};
+use crate::behaviour_tree::{BehaviourTrees, LeafContext, Leaves, Status, TreeState};
+use crate::difficulty::Difficulty;
+use crate::health::Team;
+use crate::replay::GameRng;
+use crate::weapon::{spawn_projectile, WeaponDefinitions};
+
+/// file containing the behaviour trees of the enemies, keyed by name
+pub(super) const BEHAVIOUR_TREES_PATH: &str = "assets/behaviours.ron";
+
+/// enemy driven by a behaviour tree instead of an [`super::brain::EnemyBrain`]
+#[derive(Component, Clone, Debug)]
+pub struct BehaviourAgent {
+    /// name of the tree, see [`BehaviourTrees`]
+    pub tree: String,
+    /// distance in pixels at which the player is noticed
+    pub detection_radius: f32,
+    /// distance in pixels within which `player_in_attack_range` holds
+    pub attack_radius: f32,
+    /// turning speed of `rotate_to_player` in radians per second
+    pub rotation_speed: f32,
+    state: TreeState,
+}
+
+impl BehaviourAgent {
+    pub fn new(tree: &str) -> Self {
+        Self {
+            tree: tree.to_string(),
+            detection_radius: 400.0,
+            attack_radius: 300.0,
+            rotation_speed: f32::to_radians(90.0),
+            state: TreeState::default(),
+        }
+    }
+}
+
+/// the leaves enemy trees can use
+pub(super) fn enemy_leaves() -> Leaves {
+    Leaves::default()
+        .condition("can_see_player", |context| {
+            perception(context).is_some_and(|perception| perception.can_see_player)
+        })
+        .condition("player_in_attack_range", |context| {
+            let Some(agent) = context.world.get::<BehaviourAgent>(context.entity) else {
+                return false;
+            };
+            let attack_radius = agent.attack_radius;
+            perception(context).is_some_and(|perception| {
+                perception.can_see_player && perception.distance <= attack_radius
+            })
+        })
+        .action("snap_to_player", |context| {
+            let Some(player_position) = visible_player_position(context) else {
+                return Status::Failure;
+            };
+            let Some(mut transform) = context.world.get_mut::<Transform>(context.entity) else {
+                return Status::Failure;
+            };
+            snap_towards(&mut transform, player_position);
+            Status::Success
+        })
+        .action("rotate_to_player", |context| {
+            let Some(player_position) = visible_player_position(context) else {
+                return Status::Failure;
+            };
+            let Some(agent) = context.world.get::<BehaviourAgent>(context.entity) else {
+                return Status::Failure;
+            };
+            let max_rotation = agent.rotation_speed * context.delta;
+            let Some(mut transform) = context.world.get_mut::<Transform>(context.entity) else {
+                return Status::Failure;
+            };
+            if rotate_towards(&mut transform, player_position, max_rotation) {
+                Status::Success
+            } else {
+                Status::Running
+            }
+        })
+        .action("fire", fire)
+}
+
+fn perception(context: &LeafContext) -> Option<Perception> {
+    context.world.get::<Perception>(context.entity).copied()
+}
+
+fn visible_player_position(context: &LeafContext) -> Option<Vec2> {
+    perception(context)
+        .filter(|perception| perception.can_see_player)
+        .and_then(|perception| perception.last_known_position)
+}
+
+/// shoot the turret weapon straight ahead, or start the pattern of the agent if it has one
+fn fire(context: &mut LeafContext) -> Status {
+    let entity = context.entity;
+    let world = &mut *context.world;
+    if world.get::<PatternEmitter>(entity).is_some() {
+        return world.resource_scope(|world, patterns: Mut<FirePatterns>| {
+            let mut emitter = world.get_mut::<PatternEmitter>(entity).unwrap();
+            if emitter.trigger(&patterns) {
+                Status::Success
+            } else {
+                Status::Failure
+            }
+        });
+    }
+    let (Some(turret), Some(transform)) =
+        (world.get::<Turret>(entity), world.get::<Transform>(entity))
+    else {
+        return Status::Failure;
+    };
+    let Some(weapon) = world
+        .resource::<WeaponDefinitions>()
+        .get(&turret.weapon)
+        .cloned()
+    else {
+        warn!("unknown turret weapon {}", turret.weapon);
+        return Status::Failure;
+    };
+    let position = transform.translation.xy();
+    let forward = (transform.rotation * Vec3::Y).xy();
+    let team = world.get::<Team>(entity).copied().unwrap_or(Team::Enemy);
+
+    let aim_error = world.resource::<Difficulty>().settings().aim_error;
+    let spread = world
+        .resource_mut::<GameRng>()
+        .gen_range(-aim_error..=aim_error);
+
+    let mut queue = CommandQueue::default();
+    spawn_projectile(
+        &mut Commands::new(&mut queue, world),
+        &weapon,
+        entity,
+        team,
+        position + forward * weapon.size * 3.0,
+        Vec2::from_angle(spread).rotate(forward),
+        Vec2::ZERO,
+    );
+    queue.apply(world);
+    Status::Success
+}
+
+/// tick the tree of every behaviour agent. Leaves get the whole world, so this is an exclusive
+/// system.
+pub(super) fn behaviour_tree_system(world: &mut World) {
+    let delta = world.resource::<Time>().delta_seconds();
+    let agents: Vec<Entity> = world
+        .query_filtered::<Entity, With<BehaviourAgent>>()
+        .iter(world)
+        .collect();
+
+    world.resource_scope(|world, trees: Mut<BehaviourTrees>| {
+        for entity in agents {
+            // the tree state is taken out of the agent while the leaves have the world
+            let Some(mut agent) = world.get_mut::<BehaviourAgent>(entity) else {
+                continue;
+            };
+            let tree = agent.tree.clone();
+            let mut state = std::mem::take(&mut agent.state);
+
+            let mut context = LeafContext {
+                world,
+                entity,
+                delta,
+            };
+            if trees.tick(&tree, &mut state, &mut context).is_none() {
+                warn!("unknown behaviour tree {tree}");
+            }
+
+            if let Some(mut agent) = world.get_mut::<BehaviourAgent>(entity) {
+                agent.state = state;
+            }
+        }
+    });
+}
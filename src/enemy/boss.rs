@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use super::brain::{EnemyBrain, EnemyBrainBundle};
+use super::pattern::PatternEmitter;
+use super::turret::Turret;
+use super::RotateToPlayer;
+use crate::health::{DamageMultiplier, Health, ShareDamage, Team};
+use crate::particles::{ParticleEffect, ParticlePreset};
+
+/// file containing all boss definitions, keyed by name
+pub(super) const BOSS_DEFINITIONS_PATH: &str = "assets/bosses.ron";
+
+/// all bosses known to the game, loaded from [`BOSS_DEFINITIONS_PATH`]
+#[derive(Resource, Deserialize, Default)]
+#[serde(transparent)]
+pub struct BossDefinitions(HashMap<String, BossDefinition>);
+
+impl BossDefinitions {
+    /// read and parse a ron file with boss definitions, panics like the ship definitions do
+    pub fn load(path: &str) -> Self {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("could not read boss definitions {path}: {err}"));
+        ron::from_str(&source)
+            .unwrap_or_else(|err| panic!("could not parse boss definitions {path}: {err}"))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BossDefinition> {
+        self.0.get(name)
+    }
+}
+
+/// a boss is a core with parts pinned to it. The health of the core is the health of the boss,
+/// damage to a part is dealt to the core as well.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BossDefinition {
+    pub core: BossPartDefinition,
+    pub parts: Vec<BossPartDefinition>,
+    /// phases ordered by falling health, the first one is active from the start
+    pub phases: Vec<BossPhase>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BossPartDefinition {
+    /// position of the part relative to the core in pixels, ignored for the core
+    #[serde(default)]
+    pub offset: (f32, f32),
+    pub size: (f32, f32),
+    /// mass in kilograms
+    pub mass: f32,
+    pub health: f32,
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub weak_points: Vec<WeakPointDefinition>,
+    /// whether the part carries a turret firing the weapon of the current phase
+    #[serde(default)]
+    pub turret: bool,
+}
+
+/// spot of a part that takes more damage
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct WeakPointDefinition {
+    /// position relative to the part center in pixels
+    pub offset: (f32, f32),
+    pub radius: f32,
+    /// factor on the damage of hits on the weak point
+    pub multiplier: f32,
+}
+
+/// attack behaviour of a boss while its health is in a certain range
+#[derive(Deserialize, Clone, Debug)]
+pub struct BossPhase {
+    /// the phase starts once the health fraction of the boss drops to this value
+    pub below_health: f32,
+    /// weapon of all turrets of the boss
+    pub weapon: String,
+    /// seconds between two shots of a turret
+    pub attack_cooldown: f32,
+    /// projectile pattern the turrets fire instead of single shots, see
+    /// [`super::pattern::FirePatterns`]. Its projectiles are those of the phase weapon.
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+/// a boss that is about to be assembled from its definition
+#[derive(Component)]
+pub struct BossSpawn {
+    pub name: String,
+}
+
+/// the core of a boss
+#[derive(Component)]
+pub struct Boss {
+    phases: Vec<BossPhase>,
+    /// index of the current phase
+    pub phase: usize,
+    /// point the boss hovers around
+    anchor: Vec2,
+    mass: f32,
+}
+
+/// a piece of a boss, the core is a part of itself
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BossPart {
+    pub boss: Entity,
+}
+
+/// a boss lost enough health to move on to the next phase
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BossPhaseChanged {
+    pub boss: Entity,
+    pub phase: usize,
+}
+
+/// placeholder for a boss, [`assemble_boss_system`] builds the boss around it
+pub(super) fn spawn_boss(commands: &mut Commands, name: &str, position: Vec2) -> Entity {
+    commands
+        .spawn((
+            TransformBundle::from_transform(Transform::from_translation(position.extend(0.0))),
+            BossSpawn {
+                name: name.to_string(),
+            },
+        ))
+        .id()
+}
+
+/// build the core and parts of newly spawned bosses
+pub(super) fn assemble_boss_system(
+    mut commands: Commands,
+    definitions: Res<BossDefinitions>,
+    query: Query<(Entity, &BossSpawn, &Transform), Added<BossSpawn>>,
+) {
+    for (core, spawn, transform) in &query {
+        let Some(definition) = definitions.get(&spawn.name) else {
+            warn!("unknown boss {}", spawn.name);
+            commands.entity(core).despawn();
+            continue;
+        };
+        let Some(first_phase) = definition.phases.first() else {
+            warn!("boss {} has no phases", spawn.name);
+            commands.entity(core).despawn();
+            continue;
+        };
+        let position = transform.translation.xy();
+
+        commands.entity(core).remove::<BossSpawn>().insert((
+            Boss {
+                phases: definition.phases.clone(),
+                phase: 0,
+                anchor: position,
+                mass: definition.core.mass,
+            },
+            ExternalForce::default(),
+            Velocity::default(),
+        ));
+        build_part(
+            &mut commands,
+            core,
+            core,
+            &definition.core,
+            position,
+            first_phase,
+        );
+
+        for part_definition in &definition.parts {
+            let offset = Vec2::from(part_definition.offset);
+            let part = commands
+                .spawn((
+                    ShareDamage(core),
+                    // pinned to the core at its center, turning is up to the turret
+                    ImpulseJoint::new(
+                        core,
+                        RevoluteJointBuilder::new()
+                            .local_anchor1(offset)
+                            .local_anchor2(Vec2::ZERO),
+                    ),
+                ))
+                .id();
+            build_part(
+                &mut commands,
+                part,
+                core,
+                part_definition,
+                position + offset,
+                first_phase,
+            );
+        }
+    }
+}
+
+/// add body, health, weak points and turret of a part to its entity
+fn build_part(
+    commands: &mut Commands,
+    part: Entity,
+    core: Entity,
+    definition: &BossPartDefinition,
+    position: Vec2,
+    phase: &BossPhase,
+) {
+    let size = Vec2::from(definition.size);
+    let (r, g, b) = definition.color;
+    let mut part_commands = commands.entity(part);
+    part_commands.insert((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(r, g, b),
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_translation(position.extend(0.0)),
+            ..default()
+        },
+        BossPart { boss: core },
+        Health::new(definition.health),
+        Team::Enemy,
+        RigidBody::Dynamic,
+        Collider::cuboid(size.x / 2.0, size.y / 2.0),
+        ColliderMassProperties::Mass(definition.mass),
+        // bosses float, parts only turn when their turret aims
+        GravityScale(0.0),
+        Damping {
+            linear_damping: 2.0,
+            angular_damping: 0.0,
+        },
+    ));
+
+    if definition.turret {
+        part_commands.insert((
+            Turret {
+                weapon: phase.weapon.clone(),
+                fire_tolerance: f32::to_radians(8.0),
+            },
+            EnemyBrainBundle {
+                brain: EnemyBrain {
+                    detection_radius: 700.0,
+                    attack_radius: 600.0,
+                    attack_cooldown: phase.attack_cooldown,
+                    ..default()
+                },
+                ..default()
+            },
+            RotateToPlayer {
+                rotation_speed: f32::to_radians(120.0),
+            },
+            // turned through its angular velocity
+            Velocity::default(),
+        ));
+        if let Some(pattern) = &phase.pattern {
+            part_commands.insert(PatternEmitter::with_weapon(pattern, &phase.weapon));
+        }
+    } else {
+        part_commands.insert(LockedAxes::ROTATION_LOCKED);
+    }
+
+    part_commands.with_children(|parent| {
+        for weak_point in &definition.weak_points {
+            let (x, y) = weak_point.offset;
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::YELLOW,
+                        custom_size: Some(Vec2::splat(weak_point.radius * 2.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(x, y, 0.1),
+                    ..default()
+                },
+                Collider::ball(weak_point.radius),
+                ColliderMassProperties::Mass(0.0),
+                DamageMultiplier(weak_point.multiplier),
+            ));
+        }
+    });
+}
+
+/// move bosses on to the next phase as their health drops and switch the weapons of their turrets
+pub(super) fn boss_phase_system(
+    mut commands: Commands,
+    mut bosses: Query<(Entity, &mut Boss, &Health), Changed<Health>>,
+    mut turrets: Query<(Entity, &BossPart, &Health, &mut Turret, &mut EnemyBrain)>,
+    mut phase_changed: EventWriter<BossPhaseChanged>,
+) {
+    for (entity, mut boss, health) in &mut bosses {
+        // the death system already queued the despawn of the whole boss
+        if health.is_dead() {
+            continue;
+        }
+        let fraction = health.fraction();
+        let phase = boss
+            .phases
+            .iter()
+            .rposition(|phase| fraction <= phase.below_health)
+            .unwrap_or(0);
+        // phases only ever advance, healing does not bring an earlier phase back
+        if phase <= boss.phase {
+            continue;
+        }
+        boss.phase = phase;
+        let phase_definition = &boss.phases[phase];
+
+        for (turret_entity, part, part_health, mut turret, mut brain) in &mut turrets {
+            // parts killed by the same hit are despawned, inserting into them would panic
+            if part.boss != entity || part_health.is_dead() {
+                continue;
+            }
+            turret.weapon = phase_definition.weapon.clone();
+            brain.attack_cooldown = phase_definition.attack_cooldown;
+            // a fresh emitter, the pattern of the last phase may still be running
+            match &phase_definition.pattern {
+                Some(pattern) => {
+                    commands
+                        .entity(turret_entity)
+                        .insert(PatternEmitter::with_weapon(
+                            pattern,
+                            &phase_definition.weapon,
+                        ))
+                }
+                None => commands.entity(turret_entity).remove::<PatternEmitter>(),
+            };
+        }
+        phase_changed.send(BossPhaseChanged {
+            boss: entity,
+            phase,
+        });
+    }
+}
+
+/// let the core blow up a little when the boss enters a new phase
+pub(super) fn boss_phase_effect_system(
+    mut phase_changed: EventReader<BossPhaseChanged>,
+    bosses: Query<&Transform, With<Boss>>,
+    mut effects: EventWriter<ParticleEffect>,
+) {
+    for event in phase_changed.read() {
+        let Ok(transform) = bosses.get(event.boss) else {
+            continue;
+        };
+        info!("boss {:?} entered phase {}", event.boss, event.phase);
+        effects.send(ParticleEffect {
+            preset: ParticlePreset::Explosion,
+            position: transform.translation.xy(),
+            direction: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+        });
+    }
+}
+
+/// keep bosses floating around their anchor like on a spring
+pub(super) fn boss_hover_system(
+    mut query: Query<(&Boss, &Transform, &Velocity, &mut ExternalForce)>,
+) {
+    for (boss, transform, velocity, mut external_force) in &mut query {
+        let offset = boss.anchor - transform.translation.xy();
+        let acceleration = offset * 2.0 - velocity.linvel * 1.5;
+        // bevy_rapier takes forces in pixel units, so acceleration in pixels times mass in kg
+        let force = acceleration * boss.mass;
+        if external_force.force != force {
+            external_force.force = force;
+        }
+    }
+}
+
+/// parts of a destroyed boss go down with it
+pub(super) fn orphaned_boss_part_system(
+    bosses: Query<(), With<Boss>>,
+    mut parts: Query<(&BossPart, &mut Health), Without<Boss>>,
+) {
+    for (part, mut health) in &mut parts {
+        if !bosses.contains(part.boss) && !health.is_dead() {
+            health.current = 0.0;
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+
+use super::behaviour::BehaviourAgent;
+use crate::health::Health;
+use crate::line_of_sight::LineOfSight;
+use crate::player::Player;
+
+/// what an enemy is currently doing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrainState {
+    /// the player is not around, nothing to do
+    #[default]
+    Idle,
+    /// the player was noticed or just lost, the enemy watches but does not attack
+    Alert,
+    /// the player is in range and visible, aim and fire
+    Attack,
+    /// badly damaged, stop attacking and get away
+    Flee,
+}
+
+/// state machine deciding the behaviour of an enemy
+#[derive(Component, Clone, Debug)]
+pub struct EnemyBrain {
+    pub state: BrainState,
+    /// distance in pixels at which the player is noticed
+    pub detection_radius: f32,
+    /// distance in pixels within which an alerted enemy attacks
+    pub attack_radius: f32,
+    /// health fraction below which the enemy flees
+    pub flee_health: f32,
+    /// seconds the player has to be visible before an idle enemy reacts
+    pub reaction_time: f32,
+    /// seconds an enemy stays alert after losing the player before it goes back to idle
+    pub alert_duration: f32,
+    /// seconds between two attacks
+    pub attack_cooldown: f32,
+    /// seconds until the next attack is possible
+    pub cooldown: f32,
+    /// seconds spent in the current state
+    pub time_in_state: f32,
+}
+
+impl Default for EnemyBrain {
+    fn default() -> Self {
+        Self {
+            state: BrainState::Idle,
+            detection_radius: 400.0,
+            attack_radius: 300.0,
+            flee_health: 0.25,
+            reaction_time: 0.3,
+            alert_duration: 3.0,
+            attack_cooldown: 1.0,
+            cooldown: 0.0,
+            time_in_state: 0.0,
+        }
+    }
+}
+
+impl EnemyBrain {
+    /// whether the enemy is attacking and its attack cooldown is over
+    pub fn can_attack(&self) -> bool {
+        self.state == BrainState::Attack && self.cooldown <= 0.0
+    }
+
+    /// start the cooldown after an attack was made
+    pub fn attacked(&mut self) {
+        self.cooldown = self.attack_cooldown;
+    }
+
+    fn set_state(&mut self, state: BrainState) {
+        if self.state != state {
+            self.state = state;
+            self.time_in_state = 0.0;
+        }
+    }
+}
+
+/// what an enemy knows about the player, updated once every tick before the brain runs. Other
+/// systems read the cached result instead of casting their own rays.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Perception {
+    /// whether the player is within the detection radius and not hidden behind terrain
+    pub can_see_player: bool,
+    /// distance to the player in pixels, infinite if there is no player
+    pub distance: f32,
+    /// where the player was last seen
+    pub last_known_position: Option<Vec2>,
+}
+
+/// point an enemy should face, `None` if it should not aim at all
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct AimTarget(pub Option<Vec2>);
+
+/// everything an enemy needs to think
+#[derive(Bundle, Default)]
+pub struct EnemyBrainBundle {
+    pub brain: EnemyBrain,
+    pub perception: Perception,
+    pub aim_target: AimTarget,
+}
+
+/// notice the player if it is within the detection radius and in line of sight. Enemies without a
+/// brain take the detection radius from their behaviour tree agent.
+pub(super) fn perception_system(
+    mut query: Query<
+        (
+            Option<&EnemyBrain>,
+            Option<&BehaviourAgent>,
+            &mut Perception,
+            &Transform,
+        ),
+        Without<Player>,
+    >,
+    player_query: Query<&Transform, With<Player>>,
+    line_of_sight: LineOfSight,
+) {
+    let player_translation = player_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.xy());
+
+    for (brain, agent, mut perception, transform) in &mut query {
+        let detection_radius = brain
+            .map(|brain| brain.detection_radius)
+            .or(agent.map(|agent| agent.detection_radius))
+            .unwrap_or_default();
+        let Some(player_translation) = player_translation else {
+            perception.can_see_player = false;
+            perception.distance = f32::INFINITY;
+            continue;
+        };
+        let enemy_translation = transform.translation.xy();
+        perception.distance = enemy_translation.distance(player_translation);
+        // only cast a ray if the player is close enough to matter
+        perception.can_see_player = perception.distance <= detection_radius
+            && line_of_sight.is_clear(enemy_translation, player_translation);
+        if perception.can_see_player {
+            perception.last_known_position = Some(player_translation);
+        }
+    }
+}
+
+/// run the state transitions of all enemy brains and pick their aim targets
+pub(super) fn brain_system(
+    time: Res<Time>,
+    mut query: Query<(
+        &mut EnemyBrain,
+        &Perception,
+        &mut AimTarget,
+        Option<&Health>,
+    )>,
+) {
+    let delta = time.delta_seconds();
+    for (mut brain, perception, mut aim_target, health) in &mut query {
+        brain.time_in_state += delta;
+        brain.cooldown = (brain.cooldown - delta).max(0.0);
+
+        let badly_damaged = health.is_some_and(|health| health.fraction() < brain.flee_health);
+        let in_attack_range =
+            perception.can_see_player && perception.distance <= brain.attack_radius;
+
+        let next_state = match brain.state {
+            _ if badly_damaged => BrainState::Flee,
+            BrainState::Flee => BrainState::Alert,
+            BrainState::Idle if perception.can_see_player => {
+                if brain.time_in_state >= brain.reaction_time {
+                    BrainState::Alert
+                } else {
+                    BrainState::Idle
+                }
+            }
+            // reaction only counts while the player is in view
+            BrainState::Idle => {
+                brain.time_in_state = 0.0;
+                BrainState::Idle
+            }
+            BrainState::Alert if in_attack_range => BrainState::Attack,
+            // the alert duration counts from the moment the player was lost
+            BrainState::Alert if perception.can_see_player => {
+                brain.time_in_state = 0.0;
+                BrainState::Alert
+            }
+            BrainState::Alert if brain.time_in_state >= brain.alert_duration => BrainState::Idle,
+            BrainState::Alert => BrainState::Alert,
+            BrainState::Attack if in_attack_range => BrainState::Attack,
+            BrainState::Attack => BrainState::Alert,
+        };
+        brain.set_state(next_state);
+
+        // an alerted enemy keeps watching where the player was last seen until it gives up, only
+        // attacking enemies fire
+        let target = match brain.state {
+            BrainState::Attack | BrainState::Alert => perception.last_known_position,
+            _ => None,
+        };
+        if aim_target.0 != target {
+            aim_target.0 = target;
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+use super::brain::{BrainState, EnemyBrain, EnemyBrainBundle, Perception};
+use super::turret::Turret;
+use super::RotateToPlayer;
+use crate::health::{Health, Team};
+use crate::line_of_sight::LineOfSight;
+use crate::navigation::NavGrid;
+use crate::player::Player;
+use crate::replay::GameRng;
+
+/// mobile enemy moved by steering forces. The [`EnemyBrain`] picks the behaviour, terrain
+/// avoidance and separation from other flyers are always active.
+#[derive(Component, Clone, Debug)]
+pub struct Flyer {
+    /// mass in kilograms, set on the collider so the steering forces are predictable
+    pub mass: f32,
+    /// top speed in pixels per second
+    pub max_speed: f32,
+    /// strongest steering acceleration in pixels per second squared
+    pub max_acceleration: f32,
+    /// distance in pixels at which the flyer starts braking when arriving at a point
+    pub slowing_radius: f32,
+    /// distance in pixels the flyer tries to keep to the player while attacking
+    pub standoff_distance: f32,
+    /// distance in pixels within which other flyers push this one away
+    pub separation_radius: f32,
+    /// length in pixels of the terrain feeler rays
+    pub whisker_length: f32,
+    /// current heading of the wander behaviour in radians
+    pub wander_angle: f32,
+}
+
+impl Default for Flyer {
+    fn default() -> Self {
+        Self {
+            mass: 200.0,
+            max_speed: 140.0,
+            max_acceleration: 400.0,
+            slowing_radius: 80.0,
+            standoff_distance: 150.0,
+            separation_radius: 40.0,
+            whisker_length: 60.0,
+            wander_angle: 0.0,
+        }
+    }
+}
+
+/// seconds between two path searches of a flyer following a path
+const REPLAN_INTERVAL: f32 = 1.0;
+
+/// distance in pixels the goal has to move before the path is planned again right away
+const REPLAN_DISTANCE: f32 = 32.0;
+
+/// distance in pixels at which a waypoint counts as reached
+const WAYPOINT_RADIUS: f32 = 12.0;
+
+/// route of a flyer around the terrain, planned on the [`NavGrid`]
+#[derive(Component, Default, Debug)]
+pub struct NavPath {
+    /// points still to fly through, in order
+    pub waypoints: Vec<Vec2>,
+    /// where the path leads, `None` if the flyer has nowhere to go
+    pub goal: Option<Vec2>,
+    /// seconds until the path is planned again
+    pub replan_timer: f32,
+}
+
+/// spawn a flying enemy with brain, weapon and physics body at the given position
+pub fn spawn_flyer(commands: &mut Commands, position: Vec2) -> Entity {
+    let flyer = Flyer::default();
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::ORANGE_RED,
+                    custom_size: Some(Vec2::new(12.0, 12.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(0.0)),
+                ..default()
+            },
+            EnemyBrainBundle {
+                brain: EnemyBrain {
+                    detection_radius: 500.0,
+                    attack_radius: 350.0,
+                    ..default()
+                },
+                ..default()
+            },
+            RotateToPlayer {
+                rotation_speed: f32::to_radians(180.0),
+            },
+            Turret {
+                weapon: "blaster".to_string(),
+                fire_tolerance: f32::to_radians(10.0),
+            },
+            Health::new(30.0),
+            Team::Enemy,
+            NavPath::default(),
+            (
+                RigidBody::Dynamic,
+                Collider::ball(6.0),
+                ColliderMassProperties::Mass(flyer.mass),
+                // flyers hover, they do not fall and only turn when they aim, see
+                // RotateToPlayer
+                GravityScale(0.0),
+                Damping {
+                    linear_damping: 0.5,
+                    angular_damping: 0.0,
+                },
+                Velocity::default(),
+                ExternalForce::default(),
+            ),
+            flyer,
+        ))
+        .id()
+}
+
+/// accelerate towards `target` at full speed
+pub fn seek(position: Vec2, velocity: Vec2, target: Vec2, max_speed: f32) -> Vec2 {
+    (target - position).normalize_or_zero() * max_speed - velocity
+}
+
+/// accelerate away from `threat` at full speed
+pub fn flee(position: Vec2, velocity: Vec2, threat: Vec2, max_speed: f32) -> Vec2 {
+    -seek(position, velocity, threat, max_speed) - 2.0 * velocity
+}
+
+/// like [`seek`], but slow down inside `slowing_radius` to come to a stop on the target
+pub fn arrive(
+    position: Vec2,
+    velocity: Vec2,
+    target: Vec2,
+    max_speed: f32,
+    slowing_radius: f32,
+) -> Vec2 {
+    let offset = target - position;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return -velocity;
+    }
+    let speed = max_speed * (distance / slowing_radius).min(1.0);
+    offset / distance * speed - velocity
+}
+
+/// seek the point where a moving target will be when we could reach it
+pub fn pursue(
+    position: Vec2,
+    velocity: Vec2,
+    target: Vec2,
+    target_velocity: Vec2,
+    max_speed: f32,
+) -> Vec2 {
+    let look_ahead = position.distance(target) / max_speed.max(f32::EPSILON);
+    seek(
+        position,
+        velocity,
+        target + target_velocity * look_ahead,
+        max_speed,
+    )
+}
+
+/// drift around aimlessly by slowly changing the heading
+pub fn wander(velocity: Vec2, wander_angle: &mut f32, max_speed: f32, rng: &mut impl Rng) -> Vec2 {
+    *wander_angle += rng.gen_range(-0.3..=0.3);
+    Vec2::from_angle(*wander_angle) * max_speed * 0.5 - velocity
+}
+
+/// push away from neighbours closer than `radius`, stronger the closer they are
+pub fn separation(position: Vec2, neighbours: impl Iterator<Item = Vec2>, radius: f32) -> Vec2 {
+    neighbours
+        .filter_map(|neighbour| {
+            let away = position - neighbour;
+            let distance = away.length();
+            (distance > f32::EPSILON && distance < radius)
+                .then(|| away / distance * (1.0 - distance / radius))
+        })
+        .sum()
+}
+
+/// feel ahead with three short rays and steer away from terrain they touch. Returns a unit-less
+/// urgency vector, 0 when nothing is in the way and up to ~1 per whisker right at a wall.
+fn avoid_terrain(
+    line_of_sight: &LineOfSight,
+    position: Vec2,
+    velocity: Vec2,
+    whisker_length: f32,
+) -> Vec2 {
+    let Some(heading) = velocity.try_normalize() else {
+        return Vec2::ZERO;
+    };
+    let mut avoidance = Vec2::ZERO;
+    for angle in [0.0, 0.5, -0.5_f32] {
+        let whisker = Vec2::from_angle(angle).rotate(heading);
+        // the side whiskers are shorter, they only guard the flanks
+        let length = whisker_length * if angle == 0.0 { 1.0 } else { 0.7 };
+        if let Some(distance) =
+            line_of_sight.first_terrain_hit(position, position + whisker * length)
+        {
+            let urgency = 1.0 - distance / length;
+            // steer back and towards the side the whisker is not on
+            let side = if angle >= 0.0 {
+                -whisker.perp()
+            } else {
+                whisker.perp()
+            };
+            avoidance += (side - whisker) * urgency;
+        }
+    }
+    avoidance
+}
+
+/// plan paths for flyers investigating the last known player position, which is usually around
+/// a corner. Attacking flyers see the player and pursue it directly.
+pub(super) fn flyer_path_system(
+    time: Res<Time>,
+    nav_grid: Option<Res<NavGrid>>,
+    mut query: Query<(&EnemyBrain, &Perception, &Transform, &mut NavPath), With<Flyer>>,
+) {
+    let Some(nav_grid) = nav_grid else {
+        return;
+    };
+    for (brain, perception, transform, mut path) in &mut query {
+        let position = transform.translation.xy();
+        let goal = match brain.state {
+            BrainState::Alert => perception.last_known_position,
+            _ => None,
+        };
+        let Some(goal) = goal else {
+            if path.goal.is_some() {
+                *path = NavPath::default();
+            }
+            continue;
+        };
+
+        path.replan_timer -= time.delta_seconds();
+        let goal_moved = path
+            .goal
+            .is_none_or(|old_goal| old_goal.distance(goal) > REPLAN_DISTANCE);
+        if goal_moved || path.replan_timer <= 0.0 || nav_grid.is_changed() {
+            path.waypoints = nav_grid.find_path(position, goal).unwrap_or_default();
+            path.goal = Some(goal);
+            path.replan_timer = REPLAN_INTERVAL;
+        }
+
+        // the last waypoint is kept, the flyer arrives there
+        while path.waypoints.len() > 1 && path.waypoints[0].distance(position) < WAYPOINT_RADIUS {
+            path.waypoints.remove(0);
+        }
+    }
+}
+
+/// combine the steering behaviours of every flyer into a force on its rigid body
+pub(super) fn flyer_steering_system(
+    mut query: Query<(
+        Entity,
+        &mut Flyer,
+        &EnemyBrain,
+        &Perception,
+        &Transform,
+        &Velocity,
+        &NavPath,
+        &mut ExternalForce,
+    )>,
+    player_query: Query<(&Transform, Option<&Velocity>), With<Player>>,
+    line_of_sight: LineOfSight,
+    mut rng: ResMut<GameRng>,
+) {
+    let player = player_query.get_single().ok().map(|(transform, velocity)| {
+        (
+            transform.translation.xy(),
+            velocity.map_or(Vec2::ZERO, |velocity| velocity.linvel),
+        )
+    });
+    let positions: Vec<(Entity, Vec2)> = query
+        .iter()
+        .map(|(entity, _, _, _, transform, _, _, _)| (entity, transform.translation.xy()))
+        .collect();
+
+    for (entity, mut flyer, brain, perception, transform, velocity, path, mut external_force) in
+        &mut query
+    {
+        let position = transform.translation.xy();
+        let velocity = velocity.linvel;
+
+        let behaviour = match (brain.state, perception.last_known_position, player) {
+            (BrainState::Attack, _, Some((player_position, player_velocity))) => {
+                // close in, but hold position at the standoff distance to shoot
+                if position.distance(player_position) > flyer.standoff_distance {
+                    pursue(
+                        position,
+                        velocity,
+                        player_position,
+                        player_velocity,
+                        flyer.max_speed,
+                    )
+                } else {
+                    -velocity
+                }
+            }
+            // investigate where the player was last seen, along the planned path if there is one
+            (BrainState::Alert, Some(last_known), _) => match path.waypoints.as_slice() {
+                [next, _, ..] => seek(position, velocity, *next, flyer.max_speed),
+                [last] => arrive(
+                    position,
+                    velocity,
+                    *last,
+                    flyer.max_speed,
+                    flyer.slowing_radius,
+                ),
+                [] => arrive(
+                    position,
+                    velocity,
+                    last_known,
+                    flyer.max_speed,
+                    flyer.slowing_radius,
+                ),
+            },
+            (BrainState::Flee, _, Some((player_position, _))) => {
+                flee(position, velocity, player_position, flyer.max_speed)
+            }
+            _ => {
+                let max_speed = flyer.max_speed;
+                wander(velocity, &mut flyer.wander_angle, max_speed, &mut *rng)
+            }
+        };
+
+        let neighbours = positions
+            .iter()
+            .filter(|(other, _)| *other != entity)
+            .map(|(_, position)| *position);
+        let separation = separation(position, neighbours, flyer.separation_radius);
+        let avoidance = avoid_terrain(&line_of_sight, position, velocity, flyer.whisker_length);
+
+        // avoiding walls beats everything else, then keeping apart, then the actual behaviour
+        let acceleration = (avoidance * flyer.max_acceleration * 2.0
+            + separation * flyer.max_acceleration
+            + behaviour)
+            .clamp_length_max(flyer.max_acceleration);
+
+        // bevy_rapier takes forces in pixel units, so acceleration in pixels times mass in kg
+        let force = acceleration * flyer.mass;
+        if external_force.force != force {
+            external_force.force = force;
+        }
+    }
+}
@@ -1,22 +1,160 @@
 use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
 
-use crate::player::Player;
+use crate::behaviour_tree::BehaviourTrees;
+use crate::health::{death_system, Health, Team};
+use crate::state::GameplaySet;
+
+mod behaviour;
+mod boss;
+mod brain;
+mod flyer;
+mod pattern;
+mod spawner;
+mod turret;
+pub use behaviour::BehaviourAgent;
+pub use boss::{BossPart, BossPhaseChanged};
+pub use brain::{AimTarget, EnemyBrainBundle, Perception};
+pub use flyer::Flyer;
+pub use spawner::{spawn_spawner, EnemySpawner, SpawnerDefinition};
+pub use turret::Turret;
 
 pub struct EnemyBehaviorPlugin;
 impl Plugin for EnemyBehaviorPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.insert_resource(BehaviourTrees::load(
+            behaviour::BEHAVIOUR_TREES_PATH,
+            behaviour::enemy_leaves(),
+        ))
+        .insert_resource(boss::BossDefinitions::load(boss::BOSS_DEFINITIONS_PATH))
+        .insert_resource(pattern::FirePatterns::load(pattern::FIRE_PATTERNS_PATH))
+        .add_event::<BossPhaseChanged>()
+        .add_systems(
+            FixedUpdate,
+            (
+                brain::perception_system,
+                brain::brain_system,
+                behaviour::behaviour_tree_system,
+                turret::turret_aim_system,
+                (
+                    snap_to_player_system,
+                    rotate_to_player_system.before(PhysicsSet::SyncBackend),
+                ),
+                turret::turret_fire_system,
+                pattern::pattern_system,
+            )
+                .chain()
+                .in_set(GameplaySet),
+        )
+        .add_systems(
+            FixedUpdate,
+            (spawner::spawner_system, boss::assemble_boss_system)
+                .chain()
+                .before(brain::perception_system)
+                .in_set(GameplaySet),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                boss::orphaned_boss_part_system,
+                (boss::boss_phase_system, boss::boss_phase_effect_system)
+                    .chain()
+                    .after(death_system)
+                    .before(turret::turret_fire_system),
+                boss::boss_hover_system.before(PhysicsSet::SyncBackend),
+            )
+                .in_set(GameplaySet),
+        )
+        .add_systems(
             FixedUpdate,
-            (snap_to_player_system, rotate_to_player_system),
+            (flyer::flyer_path_system, flyer::flyer_steering_system)
+                .chain()
+                .after(brain::brain_system)
+                .before(PhysicsSet::SyncBackend)
+                .in_set(GameplaySet),
         );
     }
 }
 
-/// snap to player ship behavior
+/// the kinds of enemies spawners can release
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum EnemyKind {
+    /// stationary turret that snaps to face the player
+    SnapTurret,
+    /// stationary turret turning towards the player, speed in degrees per second
+    RotatingTurret { rotation_speed: f32 },
+    /// mobile enemy chasing the player through the cave
+    Flyer,
+    /// stationary turret driven by the named behaviour tree, see [`BehaviourTrees`]
+    ScriptedTurret { tree: String },
+    /// multi-part boss, see [`boss::BossDefinitions`]
+    Boss { name: String },
+}
+
+impl EnemyKind {
+    /// whether the enemy is a stationary turret, boss turrets do not count
+    pub fn is_turret(&self) -> bool {
+        matches!(
+            self,
+            EnemyKind::SnapTurret
+                | EnemyKind::RotatingTurret { .. }
+                | EnemyKind::ScriptedTurret { .. }
+        )
+    }
+}
+
+/// spawn an enemy of the given kind at the given position
+pub fn spawn_enemy(commands: &mut Commands, kind: &EnemyKind, position: Vec2) -> Entity {
+    let (color, mut enemy) = match kind {
+        EnemyKind::SnapTurret => (
+            Color::BEIGE,
+            commands.spawn((SnapToPlayer, EnemyBrainBundle::default())),
+        ),
+        EnemyKind::RotatingTurret { rotation_speed } => (
+            Color::BISQUE,
+            commands.spawn((
+                RotateToPlayer {
+                    rotation_speed: rotation_speed.to_radians(),
+                },
+                EnemyBrainBundle::default(),
+            )),
+        ),
+        EnemyKind::Flyer => return flyer::spawn_flyer(commands, position),
+        EnemyKind::Boss { name } => return boss::spawn_boss(commands, name, position),
+        EnemyKind::ScriptedTurret { tree } => (
+            Color::SALMON,
+            commands.spawn((BehaviourAgent::new(tree), Perception::default())),
+        ),
+    };
+    enemy
+        .insert((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(10.0, 20.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(0.0)),
+                ..default()
+            },
+            Health::new(50.0),
+            Collider::cuboid(5.0, 10.0),
+            Team::Enemy,
+            Turret {
+                weapon: "turret_cannon".to_string(),
+                fire_tolerance: f32::to_radians(5.0),
+            },
+        ))
+        .id()
+}
+
+/// snap to player ship behavior, used as the aiming step of the [`brain::EnemyBrain`] while attacking
 #[derive(Component)]
 pub struct SnapToPlayer;
 
-/// rotate to face player ship behavior
+/// rotate to face player ship behavior, used as the aiming step of the [`brain::EnemyBrain`] while
+/// attacking. Rigid bodies turn through their angular velocity so rapier does not undo the turn.
 #[derive(Component)]
 pub struct RotateToPlayer {
     /// rotation speed in radians per second
@@ -24,25 +162,32 @@ pub struct RotateToPlayer {
 }
 
 /// Demonstrates snapping the enemy ship to face the player ship immediately.
-fn snap_to_player_system(
-    mut query: Query<&mut Transform, (With<SnapToPlayer>, Without<Player>)>,
-    player_query: Query<&Transform, With<Player>>,
-) {
-    let player_transform = player_query.single();
-    // get the player translation in 2D
-    let player_translation = player_transform.translation.xy();
+fn snap_to_player_system(mut query: Query<(&AimTarget, &mut Transform), With<SnapToPlayer>>) {
+    for (aim_target, mut enemy_transform) in &mut query {
+        // get the player translation in 2D, or whatever the brain wants to aim at instead
+        let Some(player_translation) = aim_target.0 else {
+            continue;
+        };
+        snap_towards(&mut enemy_transform, player_translation);
+    }
+}
 
-    for mut enemy_transform in &mut query {
-        // get the vector from the enemy ship to the player ship in 2D and normalize it.
-        let to_player = (player_translation - enemy_transform.translation.xy()).normalize();
+/// turn the enemy to face the target immediately
+pub(crate) fn snap_towards(enemy_transform: &mut Transform, player_translation: Vec2) {
+    let to_player = direction_to(enemy_transform, player_translation);
 
-        // get the quaternion to rotate from the initial enemy facing direction to the direction
-        // facing the player
-        let rotate_to_player = Quat::from_rotation_arc(Vec3::Y, to_player.extend(0.));
+    // get the quaternion to rotate from the initial enemy facing direction to the direction
+    // facing the player
+    let rotate_to_player = Quat::from_rotation_arc(Vec3::Y, to_player.extend(0.));
 
-        // rotate the enemy to face the player
-        enemy_transform.rotation = rotate_to_player;
-    }
+    // rotate the enemy to face the player
+    enemy_transform.rotation = rotate_to_player;
+}
+
+/// unit vector from the enemy to the target, the to-player direction all aiming builds on
+pub(crate) fn direction_to(enemy_transform: &Transform, player_translation: Vec2) -> Vec2 {
+    // get the vector from the enemy ship to the player ship in 2D and normalize it.
+    (player_translation - enemy_transform.translation.xy()).normalize()
 }
 
 /// Demonstrates rotating an enemy ship to face the player ship at a given rotation speed.
@@ -68,54 +213,84 @@ fn snap_to_player_system(
 /// `acos`.
 fn rotate_to_player_system(
     time: Res<Time>,
-    mut query: Query<(&RotateToPlayer, &mut Transform), Without<Player>>,
-    player_query: Query<&Transform, With<Player>>,
+    mut query: Query<(
+        &RotateToPlayer,
+        &AimTarget,
+        &mut Transform,
+        Option<&mut Velocity>,
+    )>,
 ) {
-    let player_transform = player_query.single();
-    // get the player translation in 2D
-    let player_translation = player_transform.translation.xy();
+    let delta = time.delta_seconds();
+    for (config, aim_target, mut enemy_transform, velocity) in &mut query {
+        // get the player translation in 2D, or whatever the brain wants to aim at instead
+        let max_rotation = config.rotation_speed * delta;
+        let rotation = aim_target.0.map_or(0.0, |player_translation| {
+            angle_to(&enemy_transform, player_translation).clamp(-max_rotation, max_rotation)
+        });
 
-    for (config, mut enemy_transform) in &mut query {
-        // get the enemy ship forward vector in 2D (already unit length)
-        let enemy_forward = (enemy_transform.rotation * Vec3::Y).xy();
+        match velocity {
+            // turn by exactly the rotation within the next physics step, a collision must not
+            // keep the body spinning either
+            Some(mut velocity) if delta > 0.0 => {
+                let angvel = rotation / delta;
+                if velocity.angvel != angvel {
+                    velocity.angvel = angvel;
+                }
+            }
+            _ => enemy_transform.rotate_z(rotation),
+        }
+    }
+}
 
-        // get the vector from the enemy ship to the player ship in 2D and normalize it.
-        let to_player = (player_translation - enemy_transform.translation.xy()).normalize();
+/// turn the enemy towards the target by at most `max_rotation` radians, see
+/// [`rotate_to_player_system`]. Returns whether the enemy faces the target afterwards.
+pub(crate) fn rotate_towards(
+    enemy_transform: &mut Transform,
+    player_translation: Vec2,
+    max_rotation: f32,
+) -> bool {
+    let angle = angle_to(enemy_transform, player_translation);
+    enemy_transform.rotate_z(angle.clamp(-max_rotation, max_rotation));
+    // the rotation was not limited, so the enemy now faces the target
+    max_rotation >= angle.abs()
+}
 
-        // get the dot product between the enemy forward vector and the direction to the player.
-        let forward_dot_player = enemy_forward.dot(to_player);
+/// signed angle in radians the enemy has to turn by to face the target, positive is counter
+/// clockwise
+fn angle_to(enemy_transform: &Transform, player_translation: Vec2) -> f32 {
+    // get the enemy ship forward vector in 2D (already unit length)
+    let enemy_forward = (enemy_transform.rotation * Vec3::Y).xy();
 
-        // if the dot product is approximately 1.0 then the enemy is already facing the player and
-        // we can early out.
-        if (forward_dot_player - 1.0).abs() < f32::EPSILON {
-            continue;
-        }
+    // get the vector from the enemy ship to the player ship in 2D and normalize it.
+    let to_player = (player_translation - enemy_transform.translation.xy()).normalize();
 
-        // get the right vector of the enemy ship in 2D (already unit length)
-        let enemy_right = (enemy_transform.rotation * Vec3::X).xy();
+    // get the dot product between the enemy forward vector and the direction to the player.
+    let forward_dot_player = enemy_forward.dot(to_player);
 
-        // get the dot product of the enemy right vector and the direction to the player ship.
-        // if the dot product is negative them we need to rotate counter clockwise, if it is
-        // positive we need to rotate clockwise. Note that `copysign` will still return 1.0 if the
-        // dot product is 0.0 (because the player is directly behind the enemy, so perpendicular
-        // with the right vector).
-        let right_dot_player = enemy_right.dot(to_player);
+    // if the dot product is approximately 1.0 then the enemy is already facing the player and
+    // we can early out.
+    if (forward_dot_player - 1.0).abs() < f32::EPSILON {
+        return 0.0;
+    }
 
-        // determine the sign of rotation from the right dot player. We need to negate the sign
-        // here as the 2D bevy co-ordinate system rotates around +Z, which is pointing out of the
-        // screen. Due to the right hand rule, positive rotation around +Z is counter clockwise and
-        // negative is clockwise.
-        let rotation_sign = -f32::copysign(1.0, right_dot_player);
+    // get the right vector of the enemy ship in 2D (already unit length)
+    let enemy_right = (enemy_transform.rotation * Vec3::X).xy();
 
-        // limit rotation so we don't overshoot the target. We need to convert our dot product to
-        // an angle here so we can get an angle of rotation to clamp against.
-        let max_angle = forward_dot_player.clamp(-1.0, 1.0).acos(); // clamp acos for safety
+    // get the dot product of the enemy right vector and the direction to the player ship.
+    // if the dot product is negative them we need to rotate counter clockwise, if it is
+    // positive we need to rotate clockwise. Note that `copysign` will still return 1.0 if the
+    // dot product is 0.0 (because the player is directly behind the enemy, so perpendicular
+    // with the right vector).
+    let right_dot_player = enemy_right.dot(to_player);
 
-        // calculate angle of rotation with limit
-        let rotation_angle =
-            rotation_sign * (config.rotation_speed * time.delta_seconds()).min(max_angle);
+    // determine the sign of rotation from the right dot player. We need to negate the sign
+    // here as the 2D bevy co-ordinate system rotates around +Z, which is pointing out of the
+    // screen. Due to the right hand rule, positive rotation around +Z is counter clockwise and
+    // negative is clockwise.
+    let rotation_sign = -f32::copysign(1.0, right_dot_player);
 
-        // rotate the enemy to face the player
-        enemy_transform.rotate_z(rotation_angle);
-    }
+    // convert our dot product to an angle, callers limit it so they don't overshoot the target
+    let angle = forward_dot_player.clamp(-1.0, 1.0).acos(); // clamp acos for safety
+
+    rotation_sign * angle
 }
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::direction_to;
+use crate::health::Team;
+use crate::player::Player;
+use crate::weapon::{spawn_projectile, WeaponDefinitions};
+
+/// file containing all projectile patterns, keyed by name
+pub(super) const FIRE_PATTERNS_PATH: &str = "assets/patterns.ron";
+
+/// distance in pixels from the shooter center at which pattern projectiles appear
+const MUZZLE_DISTANCE: f32 = 16.0;
+
+/// all projectile patterns known to the game, loaded from [`FIRE_PATTERNS_PATH`]
+#[derive(Resource, Deserialize, Default)]
+#[serde(transparent)]
+pub struct FirePatterns(HashMap<String, FirePattern>);
+
+impl FirePatterns {
+    /// read and parse a ron file with patterns, panics like the ship definitions do
+    pub fn load(path: &str) -> Self {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("could not read fire patterns {path}: {err}"));
+        ron::from_str(&source)
+            .unwrap_or_else(|err| panic!("could not parse fire patterns {path}: {err}"))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FirePattern> {
+        self.0.get(name)
+    }
+}
+
+/// where a pattern is centered
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PatternAim {
+    /// at the player, again for every volley
+    #[default]
+    AtPlayer,
+    /// along the facing of the shooter
+    Forward,
+}
+
+/// a declarative bullet pattern. Every activation fires `volleys` volleys of `count` projectiles
+/// fanned out over `spread` degrees. Aimed bursts, spreads, rings, spirals and sweeping beams are
+/// all combinations of these values, see `assets/patterns.ron`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct FirePattern {
+    /// weapon of the projectiles, see [`WeaponDefinitions`]
+    pub weapon: String,
+    #[serde(default)]
+    pub aim: PatternAim,
+    /// projectiles per volley
+    #[serde(default = "default_one")]
+    pub count: u32,
+    /// angle in degrees covered by a volley, 360 spreads the projectiles evenly around a ring
+    #[serde(default)]
+    pub spread: f32,
+    /// projectile speed in pixels per second, the speed of the weapon if not given
+    #[serde(default)]
+    pub speed: Option<f32>,
+    /// degrees the pattern turns after every volley, makes spirals and sweeps
+    #[serde(default)]
+    pub rotation_per_volley: f32,
+    /// if not 0, the rotation swings back and forth within this many degrees instead of going
+    /// round, like a sweeping beam
+    #[serde(default)]
+    pub sweep: f32,
+    /// volleys per activation
+    #[serde(default = "default_one")]
+    pub volleys: u32,
+    /// seconds between two volleys
+    #[serde(default)]
+    pub interval: f32,
+}
+
+fn default_one() -> u32 {
+    1
+}
+
+impl FirePattern {
+    /// directions of the projectiles of one volley around `center`, with the pattern turned by
+    /// `rotation` degrees
+    pub fn volley_directions(&self, center: Vec2, rotation: f32) -> Vec<Vec2> {
+        let rotation = if self.sweep > 0.0 {
+            // triangle wave between -sweep / 2 and sweep / 2
+            let phase = rotation.rem_euclid(2.0 * self.sweep);
+            let swing = if phase < self.sweep {
+                phase
+            } else {
+                2.0 * self.sweep - phase
+            };
+            swing - self.sweep / 2.0
+        } else {
+            rotation
+        };
+        let center = Vec2::from_angle(rotation.to_radians()).rotate(center);
+
+        let count = self.count.max(1);
+        let spread = self.spread.to_radians();
+        // a full ring would put the first and the last projectile on top of each other
+        let step = if spread >= TAU {
+            TAU / count as f32
+        } else if count > 1 {
+            spread / (count - 1) as f32
+        } else {
+            0.0
+        };
+        let first = if spread >= TAU { 0.0 } else { -spread / 2.0 };
+        (0..count)
+            .map(|index| Vec2::from_angle(first + step * index as f32).rotate(center))
+            .collect()
+    }
+}
+
+/// fires patterns for an enemy, the pattern is started by whatever decides to attack
+#[derive(Component, Clone, Debug)]
+pub struct PatternEmitter {
+    /// name of the pattern, see [`FirePatterns`]
+    pub pattern: String,
+    /// weapon of the projectiles instead of the one of the pattern, like the weapon of a boss
+    /// phase
+    pub weapon: Option<String>,
+    volleys_left: u32,
+    /// seconds until the next volley
+    timer: f32,
+    /// accumulated turn of the pattern in degrees, kept between activations so spirals go on
+    rotation: f32,
+}
+
+impl PatternEmitter {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            weapon: None,
+            volleys_left: 0,
+            timer: 0.0,
+            rotation: 0.0,
+        }
+    }
+
+    /// fire the pattern with another weapon than the one it names
+    pub fn with_weapon(pattern: &str, weapon: &str) -> Self {
+        Self {
+            weapon: Some(weapon.to_string()),
+            ..Self::new(pattern)
+        }
+    }
+
+    /// start firing the pattern, `false` if it is still busy with the last activation
+    pub fn trigger(&mut self, patterns: &FirePatterns) -> bool {
+        if self.is_firing() {
+            return false;
+        }
+        let Some(pattern) = patterns.get(&self.pattern) else {
+            warn!("unknown fire pattern {}", self.pattern);
+            return false;
+        };
+        self.volleys_left = pattern.volleys;
+        self.timer = 0.0;
+        true
+    }
+
+    pub fn is_firing(&self) -> bool {
+        self.volleys_left > 0
+    }
+}
+
+/// fire the volleys of all running patterns
+pub(super) fn pattern_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    patterns: Res<FirePatterns>,
+    weapons: Res<WeaponDefinitions>,
+    mut query: Query<(Entity, &mut PatternEmitter, &Transform, Option<&Team>)>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let player_translation = player_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.xy());
+
+    for (entity, mut emitter, transform, team) in &mut query {
+        if !emitter.is_firing() {
+            continue;
+        }
+        emitter.timer -= time.delta_seconds();
+        if emitter.timer > 0.0 {
+            continue;
+        }
+        let Some(pattern) = patterns.get(&emitter.pattern) else {
+            emitter.volleys_left = 0;
+            continue;
+        };
+        let weapon_name = emitter.weapon.as_deref().unwrap_or(&pattern.weapon);
+        let Some(weapon) = weapons.get(weapon_name) else {
+            warn!("unknown pattern weapon {weapon_name}");
+            emitter.volleys_left = 0;
+            continue;
+        };
+        let mut weapon = weapon.clone();
+        if let Some(speed) = pattern.speed {
+            weapon.projectile_speed = speed;
+        }
+
+        let position = transform.translation.xy();
+        let forward = (transform.rotation * Vec3::Y).xy();
+        let center = match pattern.aim {
+            PatternAim::AtPlayer => player_translation
+                .filter(|player_translation| player_translation.distance(position) > 1.0)
+                .map_or(forward, |player_translation| {
+                    direction_to(transform, player_translation)
+                }),
+            PatternAim::Forward => forward,
+        };
+
+        for direction in pattern.volley_directions(center, emitter.rotation) {
+            spawn_projectile(
+                &mut commands,
+                &weapon,
+                entity,
+                team.copied().unwrap_or(Team::Enemy),
+                position + direction * MUZZLE_DISTANCE,
+                direction,
+                Vec2::ZERO,
+            );
+        }
+        emitter.rotation += pattern.rotation_per_volley;
+        emitter.volleys_left -= 1;
+        emitter.timer += pattern.interval;
+    }
+}
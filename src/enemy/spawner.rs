@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::pattern::PatternEmitter;
+use super::{spawn_enemy, EnemyKind};
+use crate::player::Player;
+
+/// a point in the level emitting enemies in waves, placed by the level data
+#[derive(Deserialize, Clone, Debug)]
+pub struct SpawnerDefinition {
+    pub position: (f32, f32),
+    /// the spawner holds back while this many of its enemies are alive
+    #[serde(default = "default_max_alive")]
+    pub max_alive: u32,
+    /// start over with the first wave after the last one
+    #[serde(default)]
+    pub repeat: bool,
+    pub waves: Vec<WaveDefinition>,
+}
+
+fn default_max_alive() -> u32 {
+    8
+}
+
+/// a group of enemies released once the trigger fires
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveDefinition {
+    pub trigger: SpawnTrigger,
+    pub kind: EnemyKind,
+    pub count: u32,
+    /// seconds between two enemies of the wave
+    #[serde(default)]
+    pub interval: f32,
+    /// projectile pattern the enemies fire instead of single shots, see
+    /// [`super::pattern::FirePatterns`]
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+/// condition starting a wave
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SpawnTrigger {
+    /// seconds after the previous wave was released, or after the level started
+    Timer(f32),
+    /// the player came closer to the spawner than the distance in pixels
+    PlayerProximity(f32),
+    /// every enemy of the previous waves of this spawner is dead, fires right away for the first
+    /// wave
+    AllPreviousDead,
+}
+
+/// runtime state of a spawner placed in the level
+#[derive(Component)]
+pub struct EnemySpawner {
+    definition: SpawnerDefinition,
+    /// index of the current wave, past the end when the spawner is exhausted
+    wave: usize,
+    /// enemies of the current wave released so far, `None` while waiting for the trigger
+    released: Option<u32>,
+    /// seconds since the last wave was released or since the last enemy of the current wave
+    timer: f32,
+}
+
+impl EnemySpawner {
+    /// kinds of the enemies the spawner is still going to release, the current wave included
+    pub fn pending_kinds(&self) -> impl Iterator<Item = &EnemyKind> {
+        let waves = &self.definition.waves;
+        let start = if self.definition.repeat { 0 } else { self.wave };
+        waves.iter().skip(start).map(|wave| &wave.kind)
+    }
+}
+
+/// links an enemy to the spawner it came from, for the live count
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SpawnedBy(pub Entity);
+
+/// place a spawner, it starts waiting for its first wave right away
+pub fn spawn_spawner(commands: &mut Commands, definition: &SpawnerDefinition) -> Entity {
+    let (x, y) = definition.position;
+    commands
+        .spawn((
+            TransformBundle::from_transform(Transform::from_xyz(x, y, 0.0)),
+            EnemySpawner {
+                definition: definition.clone(),
+                wave: 0,
+                released: None,
+                timer: 0.0,
+            },
+        ))
+        .id()
+}
+
+/// fire wave triggers and release the enemies of running waves
+pub(super) fn spawner_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut spawners: Query<(Entity, &mut EnemySpawner, &Transform)>,
+    spawned: Query<&SpawnedBy>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let player_position = player_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.xy());
+
+    for (entity, mut spawner, transform) in &mut spawners {
+        let Some(wave) = spawner.definition.waves.get(spawner.wave).cloned() else {
+            continue;
+        };
+        let position = transform.translation.xy();
+        let alive = spawned
+            .iter()
+            .filter(|spawned_by| spawned_by.0 == entity)
+            .count() as u32;
+        spawner.timer += time.delta_seconds();
+
+        if spawner.released.is_none() {
+            let triggered = match wave.trigger {
+                SpawnTrigger::Timer(delay) => spawner.timer >= delay,
+                SpawnTrigger::PlayerProximity(radius) => player_position
+                    .is_some_and(|player_position| player_position.distance(position) <= radius),
+                SpawnTrigger::AllPreviousDead => alive == 0,
+            };
+            if !triggered {
+                continue;
+            }
+            // the first enemy of a wave appears right away
+            spawner.released = Some(0);
+            spawner.timer = wave.interval;
+        }
+
+        let released = spawner.released.unwrap_or_default();
+        if released < wave.count
+            && spawner.timer >= wave.interval
+            && alive < spawner.definition.max_alive
+        {
+            let enemy = spawn_enemy(&mut commands, &wave.kind, position);
+            commands.entity(enemy).insert(SpawnedBy(entity));
+            if let Some(pattern) = &wave.pattern {
+                commands.entity(enemy).insert(PatternEmitter::new(pattern));
+            }
+            spawner.released = Some(released + 1);
+            spawner.timer = 0.0;
+        }
+
+        if spawner.released == Some(wave.count) {
+            spawner.wave += 1;
+            if spawner.definition.repeat && spawner.wave == spawner.definition.waves.len() {
+                spawner.wave = 0;
+            }
+            spawner.released = None;
+            spawner.timer = 0.0;
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Velocity;
+use rand::Rng;
+
+use super::brain::{AimTarget, EnemyBrain, Perception};
+use super::pattern::{FirePatterns, PatternEmitter};
+use crate::difficulty::Difficulty;
+use crate::health::Team;
+use crate::player::Player;
+use crate::replay::GameRng;
+use crate::weapon::{spawn_projectile, WeaponDefinitions};
+
+/// stationary enemy that shoots at where the player is going to be. Turning is done by the
+/// [`super::SnapToPlayer`] or [`super::RotateToPlayer`] behaviour of the same entity. Turrets with
+/// a [`PatternEmitter`] start their pattern instead of firing a single shot.
+#[derive(Component, Clone, Debug)]
+pub struct Turret {
+    /// name of the weapon, see [`WeaponDefinitions`]
+    pub weapon: String,
+    /// largest angle in radians between the turret facing and the intercept point at which it
+    /// still fires
+    pub fire_tolerance: f32,
+}
+
+/// time `t` at which a projectile fired from the origin with `projectile_speed` meets a target
+/// at `relative_position` moving with `target_velocity`, `None` if it can never catch up.
+///
+/// Solves `|relative_position + target_velocity * t| = projectile_speed * t` for the smallest
+/// positive `t`.
+pub fn intercept_time(
+    relative_position: Vec2,
+    target_velocity: Vec2,
+    projectile_speed: f32,
+) -> Option<f32> {
+    let a = target_velocity.length_squared() - projectile_speed * projectile_speed;
+    let b = 2.0 * relative_position.dot(target_velocity);
+    let c = relative_position.length_squared();
+
+    // target and projectile are equally fast, the quadratic degenerates to a linear equation
+    if a.abs() < f32::EPSILON {
+        let t = -c / b;
+        return (b.abs() > f32::EPSILON && t > 0.0).then_some(t);
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let t1 = (-b - root) / (2.0 * a);
+    let t2 = (-b + root) / (2.0 * a);
+    [t1, t2]
+        .into_iter()
+        .filter(|&t| t > 0.0)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// replace the aim target of turrets that see the player with the intercept point of their weapon
+pub(super) fn turret_aim_system(
+    difficulty: Res<Difficulty>,
+    weapons: Res<WeaponDefinitions>,
+    mut query: Query<(&Turret, &Transform, &Perception, &mut AimTarget)>,
+    player_query: Query<Option<&Velocity>, With<Player>>,
+) {
+    let player_velocity = player_query
+        .get_single()
+        .ok()
+        .flatten()
+        .map_or(Vec2::ZERO, |velocity| velocity.linvel);
+    let settings = difficulty.settings();
+
+    for (turret, transform, perception, mut aim_target) in &mut query {
+        // a turret that lost the player keeps aiming at the last known position, there is no
+        // velocity to lead it by
+        let Some(player_position) = aim_target.0.filter(|_| perception.can_see_player) else {
+            continue;
+        };
+        let Some(weapon) = weapons.get(&turret.weapon) else {
+            continue;
+        };
+        let relative_position = player_position - transform.translation.xy();
+        let lead_velocity = player_velocity * settings.lead_factor;
+        if let Some(t) = intercept_time(relative_position, lead_velocity, weapon.projectile_speed) {
+            aim_target.0 = Some(player_position + lead_velocity * t);
+        }
+    }
+}
+
+/// fire once the turret faces the intercept point closely enough and the brain allows it
+pub(super) fn turret_fire_system(
+    mut commands: Commands,
+    difficulty: Res<Difficulty>,
+    weapons: Res<WeaponDefinitions>,
+    patterns: Res<FirePatterns>,
+    mut rng: ResMut<GameRng>,
+    mut query: Query<(
+        Entity,
+        &Turret,
+        &Transform,
+        &AimTarget,
+        &Perception,
+        &mut EnemyBrain,
+        Option<&mut PatternEmitter>,
+    )>,
+) {
+    let settings = difficulty.settings();
+
+    for (entity, turret, transform, aim_target, perception, mut brain, emitter) in &mut query {
+        let Some(target) = aim_target.0 else {
+            continue;
+        };
+        if !perception.can_see_player
+            || !brain.can_attack()
+            || brain.time_in_state < settings.reaction_time
+        {
+            continue;
+        }
+        let position = transform.translation.xy();
+        let forward = (transform.rotation * Vec3::Y).xy();
+        let Some(to_target) = (target - position).try_normalize() else {
+            continue;
+        };
+        let aim_error = forward.dot(to_target).clamp(-1.0, 1.0).acos();
+        if aim_error > turret.fire_tolerance {
+            continue;
+        }
+
+        if let Some(mut emitter) = emitter {
+            if emitter.trigger(&patterns) {
+                brain.attacked();
+            }
+            continue;
+        }
+        let Some(weapon) = weapons.get(&turret.weapon) else {
+            warn!("unknown turret weapon {}", turret.weapon);
+            continue;
+        };
+
+        let spread = rng.gen_range(-settings.aim_error..=settings.aim_error);
+        let direction = Vec2::from_angle(spread).rotate(forward);
+        spawn_projectile(
+            &mut commands,
+            weapon,
+            entity,
+            Team::Enemy,
+            position + forward * weapon.size * 3.0,
+            direction,
+            Vec2::ZERO,
+        );
+        brain.attacked();
+    }
+}
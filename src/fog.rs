@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::level::{LevelDefinition, LevelMap};
+use crate::line_of_sight::LineOfSight;
+use crate::loading::CurrentLevel;
+use crate::player::Player;
+use crate::state::{GameState, GameplaySet};
+use crate::storage::{read_ron, user_data_dir, write_ron};
+
+/// edge length in pixels of a cell of the explored mask
+const FOG_CELL_SIZE: u32 = 4;
+
+/// reveal radius of levels without fog, they are still explored for the minimap
+const DEFAULT_REVEAL_RADIUS: f32 = 200.0;
+
+/// above the terrain, ships and particles
+const FOG_Z: f32 = 5.0;
+
+const FOG_COLOR: [u8; 4] = [0, 0, 0, 255];
+const CLEAR_COLOR: [u8; 4] = [0, 0, 0, 0];
+
+pub struct FogPlugin;
+impl Plugin for FogPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AreaExplored>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::Loading,
+                    to: GameState::InGame,
+                },
+                start_exploration,
+            )
+            .add_systems(OnEnter(GameState::GameOver), save_explored)
+            .add_systems(OnEnter(GameState::LevelComplete), save_explored)
+            .add_systems(
+                OnTransition {
+                    from: GameState::Paused,
+                    to: GameState::MainMenu,
+                },
+                save_explored,
+            )
+            .add_systems(
+                Update,
+                reveal_system
+                    .run_if(resource_exists::<FogOfWar>())
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// fog of war of an exploration level, the cave starts hidden and is uncovered as far as the
+/// player ships can see
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct FogDefinition {
+    /// pixels around the player ships that are revealed, terrain blocks the view
+    #[serde(default = "default_reveal_radius")]
+    pub reveal_radius: f32,
+    /// keep the explored parts of the cave from one run to the next
+    #[serde(default)]
+    pub remember: bool,
+}
+
+fn default_reveal_radius() -> f32 {
+    DEFAULT_REVEAL_RADIUS
+}
+
+/// which parts of the level the players have seen, one bit per cell of [`FOG_CELL_SIZE`] pixels
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct ExploredMask {
+    /// size in cells
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    bits: Vec<u64>,
+}
+
+impl ExploredMask {
+    /// an unexplored mask covering a level of the given size in pixels
+    pub fn new(level_width: u32, level_height: u32) -> Self {
+        let width = level_width.div_ceil(FOG_CELL_SIZE);
+        let height = level_height.div_ceil(FOG_CELL_SIZE);
+        Self {
+            width,
+            height,
+            cell_size: FOG_CELL_SIZE,
+            bits: vec![0; ((width * height) as usize).div_ceil(64)],
+        }
+    }
+
+    /// cell covering the world position, `None` outside of the level
+    pub fn cell(&self, position: Vec2) -> Option<UVec2> {
+        let cell = (position / self.cell_size as f32).floor();
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return None;
+        }
+        let cell = cell.as_uvec2();
+        (cell.x < self.width && cell.y < self.height).then_some(cell)
+    }
+
+    pub fn is_explored_cell(&self, cell: UVec2) -> bool {
+        let index = (cell.y * self.width + cell.x) as usize;
+        self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// whether the world position was seen, everything outside the level counts as unexplored
+    pub fn is_explored(&self, position: Vec2) -> bool {
+        self.cell(position)
+            .is_some_and(|cell| self.is_explored_cell(cell))
+    }
+
+    /// whether any part of the pixel area (inclusive bounds) was seen
+    pub fn is_area_explored(&self, min: IVec2, max: IVec2) -> bool {
+        let cell_size = self.cell_size as i32;
+        let min = (min / cell_size).max(IVec2::ZERO);
+        let max = (max / cell_size).min(IVec2::new(self.width as i32, self.height as i32) - 1);
+        (min.y..=max.y)
+            .any(|y| (min.x..=max.x).any(|x| self.is_explored_cell(IVec2::new(x, y).as_uvec2())))
+    }
+
+    fn explore(&mut self, cell: UVec2) {
+        let index = (cell.y * self.width + cell.x) as usize;
+        self.bits[index / 64] |= 1 << (index % 64);
+    }
+}
+
+/// the explored mask as stored on disk. Every change of the format gets a new variant, older
+/// variants are converted when they are read.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ExploredFile {
+    V1(ExploredMask),
+}
+
+impl ExploredFile {
+    fn into_mask(self) -> ExploredMask {
+        match self {
+            ExploredFile::V1(mask) => mask,
+        }
+    }
+}
+
+/// file with the explored mask of a level, one per level definition
+fn explored_path(level: &CurrentLevel) -> PathBuf {
+    user_data_dir()
+        .join("explored")
+        .join(format!("{}.ron", level.name()))
+}
+
+/// exploration of the level being played
+#[derive(Resource)]
+pub struct FogOfWar {
+    pub reveal_radius: f32,
+    /// save the explored mask when the run ends
+    pub remember: bool,
+    /// texture of the fog over the level, only levels with fog have it
+    overlay: Option<Handle<Image>>,
+    /// cell every player ship revealed from last, nothing changes until it moves to another one
+    last_cells: HashMap<Entity, UVec2>,
+}
+
+/// cells of the explored mask were seen for the first time, `min` and `max` are the inclusive
+/// pixel bounds of the change
+#[derive(Event, Clone, Copy, Debug)]
+pub struct AreaExplored {
+    pub min: IVec2,
+    pub max: IVec2,
+}
+
+/// start tracking the explored area and cover levels with fog. Levels that remember their
+/// exploration start with the mask of the last run.
+fn start_exploration(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    level: Res<LevelMap>,
+    definition: Res<LevelDefinition>,
+    current_level: Res<CurrentLevel>,
+) {
+    let mut mask = ExploredMask::new(level.width(), level.height());
+    if definition.fog.is_some_and(|fog| fog.remember) {
+        let path = explored_path(&current_level);
+        match read_ron::<ExploredFile>(&path).map(|file| file.map(ExploredFile::into_mask)) {
+            Some(Ok(saved))
+                if (saved.width, saved.height, saved.cell_size)
+                    == (mask.width, mask.height, mask.cell_size) =>
+            {
+                mask = saved;
+            }
+            Some(Ok(_)) => warn!("explored mask {} does not fit the level", path.display()),
+            Some(Err(err)) => warn!("could not read explored mask {}: {err}", path.display()),
+            None => {}
+        }
+    }
+
+    let overlay = definition.fog.map(|_| {
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: mask.width,
+                height: mask.height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &FOG_COLOR,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        for y in 0..mask.height {
+            for x in 0..mask.width {
+                let cell = UVec2::new(x, y);
+                if mask.is_explored_cell(cell) {
+                    set_texel(&mut image, &mask, cell, CLEAR_COLOR);
+                }
+            }
+        }
+        let image = images.add(image);
+        let size = Vec2::new(level.width() as f32, level.height() as f32);
+        commands.spawn(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(size),
+                ..default()
+            },
+            texture: image.clone(),
+            transform: Transform::from_translation((size / 2.0).extend(FOG_Z)),
+            ..default()
+        });
+        image
+    });
+
+    commands.insert_resource(FogOfWar {
+        reveal_radius: definition
+            .fog
+            .map_or(DEFAULT_REVEAL_RADIUS, |fog| fog.reveal_radius),
+        remember: definition.fog.is_some_and(|fog| fog.remember),
+        overlay,
+        last_cells: HashMap::new(),
+    });
+    commands.insert_resource(mask);
+}
+
+fn set_texel(image: &mut Image, mask: &ExploredMask, cell: UVec2, color: [u8; 4]) {
+    // the level is flipped, the first image row is the top of the level
+    let row = mask.height - 1 - cell.y;
+    let offset = ((row * mask.width + cell.x) * 4) as usize;
+    image.data[offset..offset + 4].copy_from_slice(&color);
+}
+
+/// uncover the cells the player ships can see. Rays are cast around every ship up to the reveal
+/// radius, close enough together that no cell at the edge is skipped, and stop at the terrain.
+/// The terrain they hit is revealed too, so the cave walls show.
+fn reveal_system(
+    mut fog: ResMut<FogOfWar>,
+    mut mask: ResMut<ExploredMask>,
+    mut images: ResMut<Assets<Image>>,
+    line_of_sight: LineOfSight,
+    players: Query<(Entity, &Transform), With<Player>>,
+    mut explored: EventWriter<AreaExplored>,
+) {
+    let cell_size = mask.cell_size as f32;
+    for (player, transform) in &players {
+        let position = transform.translation.xy();
+        let Some(player_cell) = mask.cell(position) else {
+            continue;
+        };
+        if fog.last_cells.insert(player, player_cell) == Some(player_cell) {
+            continue;
+        }
+
+        let radius = fog.reveal_radius;
+        let rays = (TAU * radius / cell_size).ceil().max(1.0) as u32;
+        let mut revealed = vec![player_cell];
+        for ray in 0..rays {
+            let direction = Vec2::from_angle(TAU * ray as f32 / rays as f32);
+            let reach = line_of_sight
+                .first_terrain_hit(position, position + direction * radius)
+                .map_or(radius, |distance| (distance + cell_size).min(radius));
+            let mut distance = 0.0;
+            while distance <= reach {
+                if let Some(cell) = mask.cell(position + direction * distance) {
+                    revealed.push(cell);
+                }
+                distance += cell_size / 2.0;
+            }
+        }
+        revealed.retain(|&cell| !mask.is_explored_cell(cell));
+        if revealed.is_empty() {
+            continue;
+        }
+
+        let mut image = fog.overlay.as_ref().and_then(|image| images.get_mut(image));
+        let (mut min, mut max) = (UVec2::MAX, UVec2::ZERO);
+        for &cell in &revealed {
+            mask.explore(cell);
+            if let Some(image) = image.as_deref_mut() {
+                set_texel(image, &mask, cell, CLEAR_COLOR);
+            }
+            min = min.min(cell);
+            max = max.max(cell);
+        }
+        explored.send(AreaExplored {
+            min: (min * mask.cell_size).as_ivec2(),
+            max: ((max + 1) * mask.cell_size).as_ivec2() - 1,
+        });
+    }
+}
+
+/// remember the explored area of levels that keep it
+fn save_explored(
+    fog: Option<Res<FogOfWar>>,
+    mask: Option<Res<ExploredMask>>,
+    level: Res<CurrentLevel>,
+) {
+    let (Some(fog), Some(mask)) = (fog, mask) else {
+        return;
+    };
+    if !fog.remember {
+        return;
+    }
+    let path = explored_path(&level);
+    if let Err(err) = write_ron(&path, &ExploredFile::V1(mask.clone())) {
+        warn!("could not save explored mask {}: {err}", path.display());
+    }
+}
@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use serde::Deserialize;
+
+use crate::difficulty::Difficulty;
+use crate::health::{Health, Team};
+use crate::player::{player_input_system, Player};
+use crate::replay::replay_record_system;
+use crate::ship::{apply_ship_thrust_system, ShipInput};
+use crate::state::{skip_main_menu, GameState, GameplaySet};
+use crate::FIXED_UPDATE_HZ;
+
+/// updates a simulation waits for the level to finish loading before giving up
+const MAX_LOADING_UPDATES: u32 = 100_000;
+
+/// a simulation run without window or renderer, read from a ron file. The player ship is flown by
+/// the input segments instead of the keyboard.
+#[derive(Resource, Deserialize, Clone, Debug)]
+pub struct SimulationScript {
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    /// fixed update ticks to simulate once the level is loaded
+    pub steps: u32,
+    /// played back to back from the first tick, the ship drifts without input after the last one
+    #[serde(default)]
+    pub inputs: Vec<InputSegment>,
+}
+
+impl SimulationScript {
+    /// read and parse a simulation script, panics like the ship definitions do
+    pub fn load(path: &str) -> Self {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("could not read simulation script {path}: {err}"));
+        ron::from_str(&source)
+            .unwrap_or_else(|err| panic!("could not parse simulation script {path}: {err}"))
+    }
+
+    /// control input of the player ship for a tick
+    fn input_at(&self, step: u32) -> InputSegment {
+        let mut start = 0;
+        for segment in &self.inputs {
+            if step < start + segment.steps {
+                return *segment;
+            }
+            start += segment.steps;
+        }
+        InputSegment::default()
+    }
+}
+
+/// the same control input held for a number of ticks, the factors work like [`ShipInput`]
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct InputSegment {
+    pub steps: u32,
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub thrust: f32,
+    #[serde(default)]
+    pub fire: bool,
+    #[serde(default)]
+    pub tractor: bool,
+}
+
+/// fixed update ticks simulated in game so far
+#[derive(Resource, Default)]
+struct SimulationStep(u32);
+
+/// a game without window, renderer and menus, ticking exactly one fixed update per app update.
+/// Gameplay, physics and level loading are the same as in the windowed game.
+pub fn headless_app(script: SimulationScript) -> App {
+    let timestep = Duration::from_secs_f64(1.0 / FIXED_UPDATE_HZ);
+    let mut app = App::new();
+    app.add_plugins((
+        // the runner is never used, the simulation calls `App::update` itself
+        MinimalPlugins.build().disable::<ScheduleRunnerPlugin>(),
+        LogPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        bevy::input::InputPlugin,
+        AssetPlugin::default(),
+    ))
+    // ships and terrain still carry sprites, their image handles need the asset type
+    .init_asset::<Image>()
+    .insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+    .insert_resource(script.difficulty)
+    .init_resource::<SimulationStep>()
+    .add_systems(Startup, skip_main_menu)
+    .add_systems(
+        FixedUpdate,
+        scripted_input_system
+            .after(player_input_system)
+            .before(replay_record_system)
+            .before(apply_ship_thrust_system)
+            .in_set(GameplaySet),
+    )
+    .insert_resource(script);
+    crate::add_game(&mut app);
+    app
+}
+
+/// how the player fared in a simulation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimulationSummary {
+    /// fixed update ticks simulated in game
+    pub steps: u32,
+    pub state: GameState,
+    /// position of the player ship, `None` once it was destroyed
+    pub player_position: Option<Vec2>,
+    /// 0 once the player ship was destroyed
+    pub player_health: f32,
+    pub enemies_alive: usize,
+}
+
+/// load the level and simulate the script, then log how the player fared
+pub fn run(script_path: &str) {
+    let script = SimulationScript::load(script_path);
+    let steps = script.steps;
+    let mut app = headless_app(script);
+    load_level(&mut app);
+    let summary = simulate(&mut app, steps);
+    info!(
+        "simulated {} steps ({:.1} s), player health {}, {} enemies alive",
+        summary.steps,
+        summary.steps as f64 / FIXED_UPDATE_HZ,
+        summary.player_health,
+        summary.enemies_alive
+    );
+}
+
+/// update a [`headless_app`] until its level is loaded and the game runs
+pub fn load_level(app: &mut App) {
+    // `App::run` would do this, the simulation drives the updates itself instead
+    app.finish();
+    app.cleanup();
+
+    let mut loading_updates = 0;
+    while *app.world.resource::<State<GameState>>().get() != GameState::InGame {
+        app.update();
+        loading_updates += 1;
+        if loading_updates > MAX_LOADING_UPDATES {
+            panic!("level did not finish loading in {MAX_LOADING_UPDATES} updates");
+        }
+    }
+}
+
+/// simulate until `steps` ticks were played in total or the game is no longer running, e.g.
+/// because the player ship was destroyed
+pub fn simulate(app: &mut App, steps: u32) -> SimulationSummary {
+    while app.world.resource::<SimulationStep>().0 < steps
+        && *app.world.resource::<State<GameState>>().get() == GameState::InGame
+    {
+        app.update();
+    }
+    summary(&mut app.world)
+}
+
+/// override the keyboard input of the player with the script
+fn scripted_input_system(
+    script: Res<SimulationScript>,
+    mut step: ResMut<SimulationStep>,
+    mut query: Query<&mut ShipInput, With<Player>>,
+) {
+    let segment = script.input_at(step.0);
+    step.0 += 1;
+    let Ok(mut input) = query.get_single_mut() else {
+        return;
+    };
+    input.rotation_factor = segment.rotation;
+    input.movement_factor = segment.thrust;
+    input.fire = segment.fire;
+    input.tractor = segment.tractor;
+}
+
+fn summary(world: &mut World) -> SimulationSummary {
+    let player = world
+        .query_filtered::<(&Transform, &Health), With<Player>>()
+        .get_single(world)
+        .ok()
+        .map(|(transform, health)| (transform.translation.xy(), health.current));
+    let enemies_alive = world
+        .query::<(&Team, &Health)>()
+        .iter(world)
+        .filter(|(team, _)| **team == Team::Enemy)
+        .count();
+    SimulationSummary {
+        steps: world.resource::<SimulationStep>().0,
+        state: *world.resource::<State<GameState>>().get(),
+        player_position: player.map(|(position, _)| position),
+        player_health: player.map_or(0.0, |(_, health)| health),
+        enemies_alive,
+    }
+}
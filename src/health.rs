@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+
+use crate::particles::{ParticleEffect, ParticlePreset};
+use crate::state::GameplaySet;
+
+pub struct HealthPlugin;
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .add_event::<DestroyedEvent>()
+            .add_systems(
+                FixedUpdate,
+                (apply_damage_system, death_system)
+                    .chain()
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// hit points of anything that can be damaged
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    /// remaining health between 0.0 and 1.0
+    pub fn fraction(&self) -> f32 {
+        if self.max <= 0.0 {
+            return 0.0;
+        }
+        (self.current / self.max).clamp(0.0, 1.0)
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// side an entity fights for, damage between members of the same team is ignored
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
+/// scales damage dealt through this entity, e.g. the collider of a weak point
+#[derive(Component, Clone, Copy, Debug)]
+pub struct DamageMultiplier(pub f32);
+
+/// damage taken by this entity is dealt to another entity as well, e.g. boss parts hurting the boss
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ShareDamage(pub Entity);
+
+/// request to damage an entity
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    /// team of whoever caused the damage, if known
+    pub source_team: Option<Team>,
+}
+
+/// an entity ran out of health and was removed
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DestroyedEvent {
+    pub team: Option<Team>,
+    /// whole the entity was a part of, taken from [`ShareDamage`]. Parts do not count as kills of
+    /// their own, e.g. the parts of a boss that go down with it.
+    pub part_of: Option<Entity>,
+}
+
+/// apply damage to the hit entity or, for colliders without health of their own, to the closest
+/// ancestor with health. Multipliers on the way scale the damage.
+pub(crate) fn apply_damage_system(
+    mut damage_events: EventReader<DamageEvent>,
+    mut query: Query<(&mut Health, Option<&Team>, Option<&ShareDamage>)>,
+    multipliers: Query<&DamageMultiplier>,
+    parents: Query<&Parent>,
+) {
+    'events: for damage in damage_events.read() {
+        let mut amount = damage.amount;
+        let mut target = damage.target;
+        loop {
+            if let Ok(multiplier) = multipliers.get(target) {
+                amount *= multiplier.0;
+            }
+            if query.contains(target) {
+                break;
+            }
+            let Ok(parent) = parents.get(target) else {
+                continue 'events;
+            };
+            target = parent.get();
+        }
+
+        let Ok((mut health, team, share)) = query.get_mut(target) else {
+            continue;
+        };
+        if team.is_some() && team.copied() == damage.source_team {
+            continue;
+        }
+        health.current -= amount;
+
+        let Some(&ShareDamage(shared_target)) = share else {
+            continue;
+        };
+        if let Ok((mut health, _, _)) = query.get_mut(shared_target) {
+            health.current -= amount;
+        }
+    }
+}
+
+/// remove everything that ran out of health with a bang
+pub(crate) fn death_system(
+    mut commands: Commands,
+    query: Query<
+        (
+            Entity,
+            &Health,
+            &GlobalTransform,
+            Option<&Team>,
+            Option<&ShareDamage>,
+        ),
+        Changed<Health>,
+    >,
+    mut destroyed: EventWriter<DestroyedEvent>,
+    mut effects: EventWriter<ParticleEffect>,
+) {
+    for (entity, health, transform, team, share) in &query {
+        if !health.is_dead() {
+            continue;
+        }
+        let position = transform.translation().xy();
+        commands.entity(entity).despawn_recursive();
+        effects.send(ParticleEffect {
+            preset: ParticlePreset::Explosion,
+            position,
+            direction: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+        });
+        destroyed.send(DestroyedEvent {
+            team: team.copied(),
+            part_of: share.map(|share| share.0),
+        });
+    }
+}
@@ -0,0 +1,341 @@
+use bevy::prelude::*;
+
+use crate::health::Health;
+use crate::menu::despawn_screen;
+use crate::objectives::ObjectiveCompleted;
+use crate::player::{Lives, Player, PlayerCamera};
+use crate::score::Score;
+use crate::ship::Loadout;
+use crate::state::{GameState, GameplaySet};
+use crate::weapon::WeaponDefinitions;
+
+/// width of the health and reload bars in pixels
+const BAR_WIDTH: f32 = 160.0;
+
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Loading), despawn_screen::<PlayerHud>)
+            .add_systems(OnEnter(GameState::MainMenu), despawn_screen::<PlayerHud>)
+            .add_systems(
+                Update,
+                (
+                    spawn_player_hud_system,
+                    remove_player_hud_system,
+                    hud_viewport_system,
+                    health_bar_system,
+                    weapon_hud_system,
+                    score_text_system.run_if(resource_changed::<Score>()),
+                    lives_text_system.run_if(resource_changed::<Lives>()),
+                    objective_text_system,
+                )
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// HUD of one player: health, primary weapon with its ammo and reload, score, lives and the last
+/// completed objective. It covers
+/// the viewport of the player's camera, see [`PlayerCamera`], with the panel at its bottom, so
+/// with a split screen every player's HUD sits in their own view.
+#[derive(Component)]
+struct PlayerHud {
+    player: Entity,
+}
+
+/// filled part of the health bar of a player
+#[derive(Component)]
+struct HealthBar {
+    player: Entity,
+}
+
+/// name of the primary weapon of a player
+#[derive(Component)]
+struct WeaponText {
+    player: Entity,
+}
+
+/// filled part of the bar showing how far the primary weapon of a player has reloaded
+#[derive(Component)]
+struct ReloadBar {
+    player: Entity,
+}
+
+/// rounds left in the primary weapon of a player, empty for unlimited ammo
+#[derive(Component)]
+struct AmmoText {
+    player: Entity,
+}
+
+#[derive(Component)]
+struct ScoreText;
+
+#[derive(Component)]
+struct LivesText;
+
+/// label of the objective completed last
+#[derive(Component)]
+struct ObjectiveText;
+
+/// give every new player ship a HUD, filled with the current values since the update systems only
+/// react to changes
+fn spawn_player_hud_system(
+    mut commands: Commands,
+    players: Query<(Entity, &Health, &Loadout), Added<Player>>,
+    weapons: Res<WeaponDefinitions>,
+    score: Res<Score>,
+    lives: Res<Lives>,
+) {
+    for (player, health, loadout) in &players {
+        let weapon = loadout.weapons.first().map_or("", String::as_str);
+        commands
+            .spawn((
+                NodeBundle {
+                    // the whole window until the camera of the player is known
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Column,
+                        justify_content: JustifyContent::FlexEnd,
+                        ..default()
+                    },
+                    ..default()
+                },
+                PlayerHud { player },
+            ))
+            .with_children(|hud| {
+                hud.spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.0),
+                        padding: UiRect::all(Val::Px(12.0)),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.4).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_bar(
+                        parent,
+                        HealthBar { player },
+                        health.fraction(),
+                        health_color(health.fraction()),
+                    );
+                    parent.spawn((
+                        TextBundle::from_section(weapon, text_style(16.0)),
+                        WeaponText { player },
+                    ));
+                    parent.spawn((
+                        TextBundle::from_section(ammo_label(loadout, &weapons), text_style(16.0)),
+                        AmmoText { player },
+                    ));
+                    spawn_bar(
+                        parent,
+                        ReloadBar { player },
+                        reload_fraction(loadout, &weapons),
+                        Color::rgb(0.6, 0.9, 1.0),
+                    );
+                    parent.spawn((
+                        TextBundle::from_section(score_label(&score), text_style(20.0)),
+                        ScoreText,
+                    ));
+                    parent.spawn((
+                        TextBundle::from_section(lives_label(&lives), text_style(16.0)),
+                        LivesText,
+                    ));
+                    parent.spawn((
+                        TextBundle::from_section("", text_style(16.0)),
+                        ObjectiveText,
+                    ));
+                });
+            });
+    }
+}
+
+/// fit the HUD of every player into the viewport of their camera, again when the camera or its
+/// viewport changes, e.g. when the window is resized
+fn hud_viewport_system(
+    cameras: Query<(Ref<Camera>, Ref<PlayerCamera>)>,
+    mut huds: Query<(Ref<PlayerHud>, &mut Style)>,
+) {
+    for (hud, mut style) in &mut huds {
+        let Some((camera, view)) = cameras.iter().find(|(_, view)| view.player == hud.player)
+        else {
+            continue;
+        };
+        if !hud.is_added() && !camera.is_changed() && !view.is_changed() {
+            continue;
+        }
+        let Some(viewport) = camera.logical_viewport_rect() else {
+            continue;
+        };
+        style.left = Val::Px(viewport.min.x);
+        style.top = Val::Px(viewport.min.y);
+        style.width = Val::Px(viewport.width());
+        style.height = Val::Px(viewport.height());
+    }
+}
+
+fn text_style(font_size: f32) -> TextStyle {
+    TextStyle {
+        font_size,
+        color: Color::WHITE,
+        ..default()
+    }
+}
+
+/// a dark frame with a fill of the given share, `marker` goes on the fill
+fn spawn_bar(parent: &mut ChildBuilder, marker: impl Component, fraction: f32, color: Color) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(BAR_WIDTH),
+                height: Val::Px(8.0),
+                ..default()
+            },
+            background_color: Color::DARK_GRAY.into(),
+            ..default()
+        })
+        .with_children(|bar| {
+            bar.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(fraction * 100.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    ..default()
+                },
+                marker,
+            ));
+        });
+}
+
+/// green when healthy, turning red towards the end
+fn health_color(fraction: f32) -> Color {
+    Color::rgb(1.0 - fraction, fraction, 0.2)
+}
+
+/// share of the cooldown of the primary weapon that is over, 1.0 when it can fire
+fn reload_fraction(loadout: &Loadout, weapons: &WeaponDefinitions) -> f32 {
+    let (Some(name), Some(&cooldown)) = (loadout.weapons.first(), loadout.cooldowns.first()) else {
+        return 0.0;
+    };
+    match weapons.get(name) {
+        Some(weapon) if weapon.cooldown > 0.0 => 1.0 - (cooldown / weapon.cooldown).clamp(0.0, 1.0),
+        _ => 1.0,
+    }
+}
+
+fn ammo_label(loadout: &Loadout, weapons: &WeaponDefinitions) -> String {
+    let ammo = loadout
+        .weapons
+        .first()
+        .and_then(|name| weapons.get(name))
+        .and_then(|weapon| loadout.ammo_left(0, weapon));
+    ammo.map_or_else(String::new, |ammo| format!("ammo {ammo}"))
+}
+
+fn lives_label(lives: &Lives) -> String {
+    format!("lives {}", lives.0)
+}
+
+fn score_label(score: &Score) -> String {
+    if score.combo > 1.0 {
+        format!("{}  x{:.2}", score.points, score.combo)
+    } else {
+        score.points.to_string()
+    }
+}
+
+/// a destroyed player ship takes its HUD with it
+fn remove_player_hud_system(
+    mut commands: Commands,
+    mut removed: RemovedComponents<Player>,
+    huds: Query<(Entity, &PlayerHud)>,
+) {
+    for player in removed.read() {
+        for (entity, hud) in &huds {
+            if hud.player == player {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn health_bar_system(
+    players: Query<(Entity, &Health), (With<Player>, Changed<Health>)>,
+    mut bars: Query<(&HealthBar, &mut Style, &mut BackgroundColor)>,
+) {
+    for (player, health) in &players {
+        for (bar, mut style, mut color) in &mut bars {
+            if bar.player != player {
+                continue;
+            }
+            style.width = Val::Percent(health.fraction() * 100.0);
+            *color = health_color(health.fraction()).into();
+        }
+    }
+}
+
+fn weapon_hud_system(
+    players: Query<(Entity, &Loadout), (With<Player>, Changed<Loadout>)>,
+    weapons: Res<WeaponDefinitions>,
+    mut texts: Query<(&WeaponText, &mut Text), Without<AmmoText>>,
+    mut ammo_texts: Query<(&AmmoText, &mut Text), Without<WeaponText>>,
+    mut bars: Query<(&ReloadBar, &mut Style)>,
+) {
+    for (player, loadout) in &players {
+        let name = loadout.weapons.first().map_or("", String::as_str);
+        for (weapon_text, mut text) in &mut texts {
+            if weapon_text.player == player && text.sections[0].value != name {
+                text.sections[0].value = name.to_string();
+            }
+        }
+        let ammo = ammo_label(loadout, &weapons);
+        for (ammo_text, mut text) in &mut ammo_texts {
+            if ammo_text.player == player && text.sections[0].value != ammo {
+                text.sections[0].value = ammo.clone();
+            }
+        }
+        let fraction = reload_fraction(loadout, &weapons);
+        for (bar, mut style) in &mut bars {
+            if bar.player == player {
+                style.width = Val::Percent(fraction * 100.0);
+            }
+        }
+    }
+}
+
+fn score_text_system(score: Res<Score>, mut query: Query<&mut Text, With<ScoreText>>) {
+    let label = score_label(&score);
+    for mut text in &mut query {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+fn lives_text_system(lives: Res<Lives>, mut query: Query<&mut Text, With<LivesText>>) {
+    let label = lives_label(&lives);
+    for mut text in &mut query {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+fn objective_text_system(
+    mut completed: EventReader<ObjectiveCompleted>,
+    mut query: Query<&mut Text, With<ObjectiveText>>,
+) {
+    let Some(objective) = completed.read().last() else {
+        return;
+    };
+    let label = format!("completed: {}", objective.label);
+    for mut text in &mut query {
+        text.sections[0].value = label.clone();
+    }
+}
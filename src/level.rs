@@ -1,398 +1,357 @@
-use std::collections::{HashSet, VecDeque};
-
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use image::{DynamicImage, GenericImageView, Rgba};
+use serde::Deserialize;
 
-#[derive(Component)]
-struct Terrain;
+use crate::cargo::CargoDefinition;
+use crate::enemy::SpawnerDefinition;
+use crate::fog::FogDefinition;
+use crate::objectives::Objective;
+use crate::race::RaceDefinition;
+use crate::state::GameplaySet;
 
-pub fn load_level_geo_new(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
-    let mut level = image::open("assets/testworld.png").unwrap();
-    level = level.flipv();
+/// a piece of level terrain with a collider
+#[derive(Component)]
+pub(crate) struct Terrain;
+
+/// the level colour of empty space, every other colour is solid terrain
+pub(crate) const EMPTY_SPACE: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// everything placed in a level besides the terrain, loaded from a ron file in `assets/levels`
+#[derive(Resource, Deserialize, Clone, Debug)]
+pub struct LevelDefinition {
+    /// path of the level bitmap, black is empty space and every other colour is terrain
+    pub image: String,
+    /// ship definition the player flies
+    pub player_ship: String,
+    pub player_start: (f32, f32),
+    #[serde(default)]
+    pub spawners: Vec<SpawnerDefinition>,
+    /// goal of a time trial, time trials never end in levels without one
+    #[serde(default)]
+    pub finish: Option<AreaDefinition>,
+    /// track of the race mode, races are not available in levels without one
+    #[serde(default)]
+    pub race: Option<RaceDefinition>,
+    /// pods to tow to the cargo goal
+    #[serde(default)]
+    pub cargo: Vec<CargoDefinition>,
+    #[serde(default)]
+    pub cargo_goal: Option<AreaDefinition>,
+    /// completing it wins the level in the campaign, levels without one are played until the
+    /// player is destroyed
+    #[serde(default)]
+    pub objective: Option<Objective>,
+    /// completing it loses the level, like a time limit
+    #[serde(default)]
+    pub failure: Option<Objective>,
+    /// hides the cave until the player ships saw it, for exploration levels
+    #[serde(default)]
+    pub fog: Option<FogDefinition>,
 }
 
-/// Walk the perimeter of a pixel group, returning a list of points forming a
-/// complete outline of the pixels. This outline composes the coordinates of
-/// the "lines" between the pixels. The upper left corner of a pixel has the
-/// coordinate offset (0.0, 0.0) and the lower right corner has the coordinate
-/// offset (1.0, 1.0).
-///
-///
-/// Example:
-/// x inside pixel(of the same color as the start)
-/// o outside pixel
-/// | perimeter
-/// coordinates start at top left
-///
-/// x | x | o
-/// --+---+--
-/// x | x | o
-/// --+---+--
-/// o | o | o
-///
-/// returns [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)]
-///
-/// o | o | o
-/// --+---+--
-/// o | x | x
-/// --+---+--
-/// o | x | x
-///
-/// returns [(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0), (1.0, 1.0)]
-///
-/// o | o | o
-/// --+---+--
-/// o | x | o
-/// --+---+--
-/// o | o | o
-///     
-/// returns [(1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (1.0, 2.0), (1.0, 1.0)]
-///
-fn walk_pixel_group_perimeter(
-    image: &DynamicImage,
-    start_pixel_x: u32,
-    start_pixel_y: u32,
-) -> Vec<Vec2> {
-    // we start at the left upper corner of the start_pixel, which by definition is inside the pixel group
-    let mut current_pixel_x = start_pixel_x as i32;
-    let mut current_pixel_y = start_pixel_y as i32;
-
-    // try to find the first pixel corner that is not borderered by a pixel of the same color
-    // from that corner we start our walk
-    // if the pixel left of the current pixel is of the same color, we can not walk from the top left to the bottom left (downwards)
-    // if the pixel above the current pixel is of the same color, we can not walk from the top left to the top right (rightwards)
-    // if the pixel right of the current pixel is of the same color, we can not walk from the top right to the bottom right (downwards)
-    // if the pixel below the current pixel is of the same color, we can not walk from the bottom left to the bottom right (rightwards)
-    // one of these four cases must be false otherwise our start is inside a group of pixels of the same color and therefore not usable as a start pixel
-
-    // check if the pixel left of the current pixel is of the same color
-    let mut left_pixel_x = current_pixel_x - 1;
-    let mut left_pixel_y = current_pixel_y;
-    let mut left_pixel_is_inner_pixel = is_inner_pixel(
-        left_pixel_x as u32,
-        left_pixel_y as u32,
-        image,
-        image.get_pixel(start_pixel_x, start_pixel_y),
-    );
-    // check if the pixel above the current pixel is of the same color
-    let mut top_pixel_x = current_pixel_x;
-    let mut top_pixel_y = current_pixel_y + 1;
-    let mut top_pixel_is_inner_pixel = is_inner_pixel(
-        top_pixel_x as u32,
-        top_pixel_y as u32,
-        image,
-        image.get_pixel(start_pixel_x, start_pixel_y),
-    );
-    // check if the pixel right of the current pixel is of the same color
-    let mut right_pixel_x = current_pixel_x + 1;
-    let mut right_pixel_y = current_pixel_y;
-    let mut right_pixel_is_inner_pixel = is_inner_pixel(
-        right_pixel_x as u32,
-        right_pixel_y as u32,
-        image,
-        image.get_pixel(start_pixel_x, start_pixel_y),
-    );
-    // check if the pixel below the current pixel is of the same color
-    let mut bottom_pixel_x = current_pixel_x;
-    let mut bottom_pixel_y = current_pixel_y - 1;
-    let mut bottom_pixel_is_inner_pixel = is_inner_pixel(
-        bottom_pixel_x as u32,
-        bottom_pixel_y as u32,
-        image,
-        image.get_pixel(start_pixel_x, start_pixel_y),
-    );
-
-    return Vec::new();
+/// circular area of the level, like the finish of a time trial
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct AreaDefinition {
+    pub position: (f32, f32),
+    /// pixels
+    pub radius: f32,
 }
 
-/// Check if a pixel is an inner pixel of a pixel group.
-/// A pixel is an inner pixel if it has the same color as the pixel group and
-/// is inside the boundaries of the image.
-fn is_inner_pixel(x: u32, y: u32, image: &DynamicImage, inner_color: Rgba<u8>) -> bool {
-    if x < 0 || y < 0 || x > image.width() || y > image.height() {
-        return false;
+impl LevelDefinition {
+    /// read and parse a level file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read level {path}: {err}"))?;
+        ron::from_str(&source).map_err(|err| format!("could not parse level {path}: {err}"))
     }
-
-    let pixel = image.get_pixel(x, y);
-    pixel == inner_color
 }
 
-pub fn load_level_geo(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
-    let mut level = image::open("assets/testworld.png").unwrap();
-    level = level.flipv();
-
-    let (level_width, level_height) = level.dimensions();
-
-    // Make black pixels transparent and create a collider for each line
-    for y in 0..level_height {
-        let mut current_color = Rgba([0, 0, 0, 255]);
-        let mut line_start = Vec2::new(0.0, y as f32);
-
-        for x in 0..level_width {
-            let pixel = level.get_pixel(x, y);
-
-            // Check if the pixel is not black
-            if pixel != current_color {
-                if current_color != Rgba([0, 0, 0, 255]) {
-                    // Create a sprite with collider for the last color
-                    let line_width = x as f32 - line_start.x;
-                    commands.spawn((
-                        SpriteBundle {
-                            sprite: Sprite {
-                                color: Color::rgb(
-                                    current_color.0[0] as f32 / 255.0,
-                                    current_color.0[1] as f32 / 255.0,
-                                    current_color.0[2] as f32 / 255.0,
-                                ),
-                                custom_size: Some(Vec2::new(line_width, 1.0)),
-                                ..default()
-                            },
-                            transform: Transform::from_translation(Vec3::new(
-                                line_start.x + line_width / 2.0,
-                                line_start.y + 0.5, // Adjust as needed
-                                0.0,
-                            )),
-                            ..default()
-                        },
-                        Terrain,
-                        Collider::cuboid(line_width / 2.0, 0.5),
-                    ));
-                }
+/// pixel data of the level for gameplay code that needs to query or change the terrain without
+/// going through rapier. One pixel of the (vertically flipped) level image is one world unit,
+/// pixel (x, y) covers the world area from (x, y) to (x + 1, y + 1).
+///
+/// Every row of pixels owns the terrain entities (sprite per run of equally coloured pixels,
+/// collider for the runs on the outline) spawned for it, so changing a pixel only rebuilds its row
+/// and the ones next to it.
+#[derive(Resource)]
+pub struct LevelMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<Rgba<u8>>,
+    rows: Vec<Vec<Entity>>,
+}
 
-                // Start a new line
-                line_start = Vec2::new(x as f32, y as f32);
-                current_color = pixel;
-            }
+impl LevelMap {
+    /// build the map from the flipped level image, no terrain entities are spawned yet
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let (width, height) = image.dimensions();
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| image.get_pixel(x, y))
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+            rows: vec![Vec::new(); height as usize],
         }
+    }
 
-        // Create a collider for the last line in the row
-        if current_color != Rgba([0, 0, 0, 255]) {
-            let line_width = (level_width - 1) as f32 - line_start.x;
-            commands.spawn((
-                SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::rgb(
-                            current_color.0[0] as f32 / 255.0,
-                            current_color.0[1] as f32 / 255.0,
-                            current_color.0[2] as f32 / 255.0,
-                        ),
-                        custom_size: Some(Vec2::new(line_width, 1.0)),
-                        ..default()
-                    },
-                    transform: Transform::from_translation(Vec3::new(
-                        line_start.x + line_width / 2.0,
-                        line_start.y + 0.5, // Adjust as needed
-                        0.0,
-                    )),
-                    ..default()
-                },
-                Terrain,
-                Collider::cuboid(line_width / 2.0, 0.5),
-            ));
+    /// colour of the pixel at the given coordinates, `None` outside of the level
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Rgba<u8>> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
         }
+        Some(self.pixels[(y as u32 * self.width + x as u32) as usize])
     }
 
-    // add the bitmap as a large background sprite
-    // offset the sprite by half the size of the image to center it
-    // flip the image vertically to match the coordinate system
-    // let offset = Vec3::new(level.width() as f32, level.height() as f32, 0.0) / 2.0;
-    // commands.spawn(SpriteBundle {
-    //     texture: images.add(bevy::render::texture::Image::from_dynamic(
-    //         level.flipv(),
-    //         true,
-    //     )),
-    //     transform: Transform {
-    //         translation: offset,
-    //         ..Default::default()
-    //     },
-    //     ..Default::default()
-    // });
-}
-
-pub fn load_level_geo_old(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
-    let level = image::open("assets/testworld.png").unwrap();
+    /// whether the pixel at the given coordinates is terrain, everything outside the level is empty
+    pub fn is_solid_pixel(&self, x: i32, y: i32) -> bool {
+        self.pixel(x, y).is_some_and(|pixel| pixel != EMPTY_SPACE)
+    }
 
-    let mut visited = HashSet::new();
-    let mut groups: Vec<Vec<Vec2>> = Vec::new();
+    /// whether the world position is inside terrain
+    pub fn is_solid(&self, position: Vec2) -> bool {
+        self.is_solid_pixel(position.x.floor() as i32, position.y.floor() as i32)
+    }
 
-    // Iterate over non-black pixels and create entities with colliders
-    for (x, y, pixel) in level.pixels() {
-        if (pixel.0[0] > 0 || pixel.0[1] > 0 || pixel.0[2] > 0) && !visited.contains(&(x, y)) {
-            let group = find_group(&level, x, y, &mut visited);
-            groups.push(group);
+    /// change a pixel, the caller has to rebuild the row afterwards. Returns false if the
+    /// coordinates are outside of the level.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Rgba<u8>) -> bool {
+        if self.pixel(x, y).is_none() {
+            return false;
         }
+        self.pixels[(y as u32 * self.width + x as u32) as usize] = color;
+        true
     }
 
-    // Create a convex hull for each group
-    for group in groups {
-        let mut outer_pixels = find_outer_pixels(&level, &group);
-        // simplyfiy the polygon by removing points that are too close to each other
-        outer_pixels = simplify_polygon(&outer_pixels, 2.0);
-        // add the first coordinate again as the last to close the polygon
-        outer_pixels.push(outer_pixels[0]);
-        info!("Outer pixels: {:?}", outer_pixels.len());
-        spawn_collider_from_vertices_polyline(&mut commands, &outer_pixels)
+    pub fn width(&self) -> u32 {
+        self.width
     }
 
-    // add the bitmap as a large background sprite
-    // offset the sprite by half the size of the image to center it
-    let offset = Vec3::new(level.width() as f32, level.height() as f32, 0.0) / 2.0;
-    commands.spawn(SpriteBundle {
-        texture: images.add(bevy::render::texture::Image::from_dynamic(level, true)),
-        transform: Transform {
-            translation: offset,
-            ..Default::default()
-        },
-        ..Default::default()
-    });
-}
-
-fn spawn_collider_from_vertices_polyline(commands: &mut Commands, vertices: &[Vec2]) {
-    commands.spawn((
-        TransformBundle::from(Transform::from_xyz(0.0, 0.0, 0.0)),
-        Collider::polyline(vertices.into(), None),
-    ));
-}
-
-fn spawn_collider_from_vertices_convex_decompose(commands: &mut Commands, vertices: &[Vec2]) {
-    commands.spawn((
-        TransformBundle::from(Transform::from_xyz(0.0, 0.0, 0.0)),
-        Collider::convex_decomposition_with_params(vertices, &[], &VHACDParameters::default()),
-    ));
-}
+    pub fn height(&self) -> u32 {
+        self.height
+    }
 
-// Function to simplify a polygon by removing points that are too close to each other
-fn simplify_polygon(polygon: &[Vec2], threshold: f32) -> Vec<Vec2> {
-    let mut simplified_polygon = Vec::new();
-    let mut last_point = polygon[0];
-    simplified_polygon.push(last_point);
+    /// register the terrain entities spawned for a row while the level is being loaded
+    pub(crate) fn set_row_entities(&mut self, y: u32, entities: Vec<Entity>) {
+        self.rows[y as usize] = entities;
+    }
 
-    for &point in polygon {
-        let distance = (point - last_point).length();
-        if distance > threshold {
-            simplified_polygon.push(point);
-            last_point = point;
+    /// despawn the terrain entities of a row and spawn them again from the current pixels
+    pub fn rebuild_row(&mut self, commands: &mut Commands, y: u32) {
+        for entity in self.rows[y as usize].drain(..) {
+            commands.entity(entity).despawn();
         }
+        let entities = spawn_terrain_row(commands, self, y);
+        self.rows[y as usize] = entities;
     }
-
-    simplified_polygon
 }
 
-// Modified flood-fill algorithm to find non-black pixels and group them
-fn find_group(
-    image: &DynamicImage,
-    start_x: u32,
-    start_y: u32,
-    visited: &mut HashSet<(u32, u32)>,
-) -> Vec<Vec2> {
-    let mut group = Vec::new();
-    let mut queue = VecDeque::new();
-    queue.push_back((start_x as i32, start_y as i32));
+/// carve a circular hole into the terrain
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CarveTerrain {
+    pub center: Vec2,
+    /// radius in pixels
+    pub radius: f32,
+}
 
-    while let Some((x, y)) = queue.pop_front() {
-        if visited.contains(&(x as u32, y as u32)) {
-            continue;
-        }
+/// terrain pixels were removed by a [`CarveTerrain`] request
+#[derive(Event, Clone, Debug)]
+pub struct TerrainCarved {
+    pub center: Vec2,
+    /// coordinates and former colour of every removed pixel
+    pub pixels: Vec<(IVec2, Rgba<u8>)>,
+}
 
-        visited.insert((x as u32, y as u32));
+/// some pixels of the level changed, `min` and `max` are the inclusive pixel bounds of the change
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TerrainChanged {
+    pub min: IVec2,
+    pub max: IVec2,
+}
 
-        let is_non_black_pixel = is_non_black_pixel(image, x, y);
-        if is_non_black_pixel {
-            group.push(Vec2::new(x as f32, y as f32));
-        }
+pub struct LevelPlugin;
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CarveTerrain>()
+            .add_event::<TerrainCarved>()
+            .add_event::<TerrainChanged>()
+            // rows are despawned and spawned again. The commands have to be applied at the end of
+            // the tick, if rapier initialises a collider in the same flush as its despawn it
+            // inserts into an entity that does not exist anymore.
+            .add_systems(
+                FixedUpdate,
+                carve_terrain_system
+                    .after(PhysicsSet::Writeback)
+                    .in_set(GameplaySet),
+            );
+    }
+}
 
-        for dx in -1..=1 {
-            for dy in -1..=1 {
-                if dx == 0 && dy == 0 {
+/// remove the terrain pixels inside the requested circles and rebuild the touched rows
+pub(crate) fn carve_terrain_system(
+    mut commands: Commands,
+    mut level: Option<ResMut<LevelMap>>,
+    mut requests: EventReader<CarveTerrain>,
+    mut carved: EventWriter<TerrainCarved>,
+    mut changed: EventWriter<TerrainChanged>,
+) {
+    let Some(level) = level.as_deref_mut() else {
+        requests.clear();
+        return;
+    };
+
+    for request in requests.read() {
+        let min = (request.center - request.radius).floor().as_ivec2();
+        let max = (request.center + request.radius).ceil().as_ivec2();
+        let mut removed = Vec::new();
+        let mut changed_rows = Vec::new();
+
+        for y in min.y..=max.y {
+            let mut row_changed = false;
+            for x in min.x..=max.x {
+                // test the pixel center against the circle
+                let pixel_center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                if pixel_center.distance_squared(request.center) > request.radius * request.radius {
                     continue;
                 }
-
-                let nx = x + dx;
-                let ny = y + dy;
-
-                if nx >= 0 && ny >= 0 && nx < image.width() as i32 && ny < image.height() as i32 {
-                    let pixel = image.get_pixel(nx as u32, ny as u32);
-                    if pixel.0[0] > 0 || pixel.0[1] > 0 || pixel.0[2] > 0 {
-                        queue.push_back((nx, ny));
-                    }
-                }
+                let Some(color) = level.pixel(x, y).filter(|&pixel| pixel != EMPTY_SPACE) else {
+                    continue;
+                };
+                level.set_pixel(x, y, EMPTY_SPACE);
+                removed.push((IVec2::new(x, y), color));
+                row_changed = true;
+            }
+            if row_changed {
+                changed_rows.push(y);
             }
         }
-    }
+        // runs above and below the hole may have been inside the terrain without a collider
+        let mut rebuild: Vec<i32> = changed_rows
+            .iter()
+            .flat_map(|&y| [y - 1, y, y + 1])
+            .filter(|&y| y >= 0 && y < level.height() as i32)
+            .collect();
+        rebuild.sort_unstable();
+        rebuild.dedup();
+        for y in rebuild {
+            level.rebuild_row(&mut commands, y as u32);
+        }
 
-    group
+        if removed.is_empty() {
+            continue;
+        }
+        changed.send(TerrainChanged { min, max });
+        carved.send(TerrainCarved {
+            center: request.center,
+            pixels: removed,
+        });
+    }
 }
 
-// Function to determine if a pixel is a non-black pixel
-fn is_non_black_pixel(image: &DynamicImage, x: i32, y: i32) -> bool {
-    let pixel = image.get_pixel(x as u32, y as u32);
-    pixel.0[0] > 0 || pixel.0[1] > 0 || pixel.0[2] > 0
+/// horizontal run of equally coloured terrain pixels, `end` is exclusive
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TerrainRun {
+    pub(crate) start: u32,
+    pub(crate) end: u32,
+    pub(crate) color: Rgba<u8>,
 }
 
-// Function to find the outer pixels of a group and order them in counter-clockwise direction
-fn find_outer_pixels(image: &DynamicImage, group: &[Vec2]) -> Vec<Vec2> {
-    let centroid = calculate_centroid(group);
-
-    // Filter and sort points by angle in counter-clockwise order
-    let mut points_with_angles: Vec<(f32, Vec2)> = group
-        .iter()
-        .filter(|&&point| is_outer_pixel(image, point.x as i32, point.y as i32))
-        .map(|&point| {
-            let angle = calculate_angle(&centroid, &point);
-            (angle, point)
-        })
-        .collect();
-
-    points_with_angles.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-
-    let mut outer_pixels = Vec::new();
-    outer_pixels.extend(points_with_angles.into_iter().map(|(_, point)| point));
-    outer_pixels
-}
-
-// Function to determine if a pixel is an outer boundary pixel
-fn is_outer_pixel(image: &DynamicImage, x: i32, y: i32) -> bool {
-    // Check if the pixel is at the image boundary or if it's a black pixel
-    if x == 0 || y == 0 || x == (image.width() as i32 - 1) || y == (image.height() as i32 - 1) {
-        return true;
+impl TerrainRun {
+    /// collider covering the run, centered on the run like its sprite
+    pub(crate) fn collider(&self) -> Collider {
+        Collider::cuboid((self.end - self.start) as f32 / 2.0, 0.5)
     }
 
-    for dx in -1..=1 {
-        for dy in -1..=1 {
-            if dx == 0 && dy == 0 {
-                continue;
-            }
-
-            let nx = x + dx;
-            let ny = y + dy;
-
-            if nx < 0 || ny < 0 || nx >= image.width() as i32 || ny >= image.height() as i32 {
-                return true; // Pixel is at the image boundary
-            }
+    /// whether empty space touches the run in row `y`. Only runs on the outline of the terrain
+    /// need a collider, anything reaching the inside has to pass the outline first.
+    pub(crate) fn is_on_outline(&self, level: &LevelMap, y: u32) -> bool {
+        let y = y as i32;
+        let (start, end) = (self.start as i32, self.end as i32);
+        !level.is_solid_pixel(start - 1, y)
+            || !level.is_solid_pixel(end, y)
+            || (start..end)
+                .any(|x| !level.is_solid_pixel(x, y - 1) || !level.is_solid_pixel(x, y + 1))
+    }
+}
 
-            let pixel = image.get_pixel(nx as u32, ny as u32);
-            if pixel.0[0] == 0 && pixel.0[1] == 0 && pixel.0[2] == 0 {
-                return true; // Pixel is a black pixel
+/// split a row of the level into runs of equally coloured terrain pixels
+pub(crate) fn terrain_runs(level: &LevelMap, y: u32) -> Vec<TerrainRun> {
+    let mut runs = Vec::new();
+    let mut current_color = EMPTY_SPACE;
+    let mut line_start = 0;
+    for x in 0..level.width {
+        let pixel = level.pixels[(y * level.width + x) as usize];
+        if pixel != current_color {
+            if current_color != EMPTY_SPACE {
+                runs.push(TerrainRun {
+                    start: line_start,
+                    end: x,
+                    color: current_color,
+                });
             }
+            // Start a new line
+            line_start = x;
+            current_color = pixel;
         }
     }
-
-    false
+    // the last line in the row stops one pixel short of the right edge, as it always did, so
+    // the collider geometry of existing levels stays the same
+    let end = level.width.saturating_sub(1);
+    if current_color != EMPTY_SPACE && line_start < end {
+        runs.push(TerrainRun {
+            start: line_start,
+            end,
+            color: current_color,
+        });
+    }
+    runs
 }
 
-// Function to calculate the centroid of a group of points
-fn calculate_centroid(group: &[Vec2]) -> Vec2 {
-    let mut centroid = Vec2::ZERO;
-    for &point in group {
-        centroid += point;
-    }
-    centroid / group.len() as f32
+/// spawn one sprite for every run of equally coloured terrain pixels in a row, with a collider
+/// if it is on the outline
+fn spawn_terrain_row(commands: &mut Commands, level: &LevelMap, y: u32) -> Vec<Entity> {
+    terrain_runs(level, y)
+        .iter()
+        .map(|run| {
+            let collider = run.is_on_outline(level, y).then(|| run.collider());
+            spawn_terrain_run(commands, y, run, collider)
+        })
+        .collect()
 }
 
-// Function to calculate the angle between two points in counter-clockwise direction
-fn calculate_angle(origin: &Vec2, point: &Vec2) -> f32 {
-    let dx = point.x - origin.x;
-    let dy = point.y - origin.y;
-    dy.atan2(dx)
+/// spawn the sprite and collider of a single run of terrain pixels in row `y`
+pub(crate) fn spawn_terrain_run(
+    commands: &mut Commands,
+    y: u32,
+    run: &TerrainRun,
+    collider: Option<Collider>,
+) -> Entity {
+    let line_width = (run.end - run.start) as f32;
+    let mut entity = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(
+                    run.color.0[0] as f32 / 255.0,
+                    run.color.0[1] as f32 / 255.0,
+                    run.color.0[2] as f32 / 255.0,
+                ),
+                custom_size: Some(Vec2::new(line_width, 1.0)),
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(
+                run.start as f32 + line_width / 2.0,
+                y as f32 + 0.5,
+                0.0,
+            )),
+            ..default()
+        },
+        Terrain,
+    ));
+    if let Some(collider) = collider {
+        entity.insert(collider);
+    }
+    entity.id()
 }
@@ -0,0 +1,164 @@
+//
+
+// This lint usually gives bad advice in the context of Bevy -- hiding complex queries behind
+// type aliases tends to obfuscate code while offering no improvement in code cleanliness.
+#![allow(clippy::type_complexity)]
+
+mod behaviour_tree;
+mod cargo;
+mod debris;
+mod enemy;
+mod fog;
+use crate::enemy::spawn_spawner;
+mod difficulty;
+pub mod headless;
+mod health;
+mod hud;
+mod player;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+mod level;
+use crate::level::LevelDefinition;
+mod line_of_sight;
+mod loading;
+mod menu;
+mod minimap;
+mod navigation;
+mod objectives;
+pub mod state;
+use crate::state::{GameMode, GameState};
+mod particles;
+mod race;
+mod replay;
+mod score;
+use crate::replay::{Replay, ReplayPlayback};
+mod ship;
+mod storage;
+mod time_trial;
+use crate::ship::ShipDefinitions;
+mod weapon;
+
+/// scale between rapier's physical units and the pixels of the level
+pub const PIXELS_PER_METER: f32 = 100.0;
+
+/// rate of the FixedUpdate schedule, gameplay steps at this rate and physics advances once a tick
+pub(crate) const FIXED_UPDATE_HZ: f64 = 60.0;
+
+/// rate of the rapier steps. It does not follow the fixed schedule: rapier splits the velocity of a
+/// step into substeps to move bodies but damps it once per step, so a different step length would
+/// give a different trajectory for the same forces.
+pub(crate) const PHYSICS_STEPS_PER_SECOND: f64 = 240.0;
+
+/// duration of a number of FixedUpdate ticks, for race clocks
+pub(crate) fn ticks_to_seconds(ticks: u32) -> f64 {
+    ticks as f64 / FIXED_UPDATE_HZ
+}
+
+/// start the game with the command line arguments. `--headless <script.ron>` simulates a script
+/// without a window, see `headless.rs`, and `--replay <replay.ron>` plays back a recorded run.
+pub fn run() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut app = App::new();
+    match args.as_slice() {
+        [flag, script_path] if flag == "--headless" => {
+            headless::run(script_path);
+            return;
+        }
+        [flag, replay_path] if flag == "--replay" => {
+            app.insert_resource(ReplayPlayback::new(Replay::load(replay_path)))
+                .add_systems(Startup, state::skip_main_menu);
+        }
+        _ => {}
+    }
+
+    app.add_plugins((
+        DefaultPlugins.set(ImagePlugin::default_nearest()),
+        menu::MenuPlugin,
+        //RapierDebugRenderPlugin::default(),
+    ))
+    .add_systems(Startup, spawn_camera);
+    add_game(&mut app);
+    //app.add_systems(Startup, setup_physics_demo);
+    app.run();
+}
+
+/// everything that makes up the game except window, rendering and menus, shared by the windowed
+/// game and the headless simulation
+pub(crate) fn add_game(app: &mut App) {
+    app.add_plugins((
+        state::GameStatePlugin,
+        ship::ShipPlugin,
+        player::PlayerPlugin,
+        enemy::EnemyBehaviorPlugin,
+        particles::ParticlePlugin,
+        level::LevelPlugin,
+        loading::LoadingPlugin,
+        debris::DebrisPlugin,
+        health::HealthPlugin,
+        weapon::WeaponPlugin,
+        navigation::NavigationPlugin,
+        RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(PIXELS_PER_METER).in_fixed_schedule(),
+    ))
+    // game modes and the systems around them
+    .add_plugins((
+        replay::ReplayPlugin,
+        time_trial::TimeTrialPlugin,
+        race::RacePlugin,
+        cargo::CargoPlugin,
+        objectives::ObjectivesPlugin,
+        score::ScorePlugin,
+        hud::HudPlugin,
+        minimap::MinimapPlugin,
+        fog::FogPlugin,
+    ))
+    .insert_resource(Time::<Fixed>::from_hz(FIXED_UPDATE_HZ))
+    .init_resource::<difficulty::Difficulty>()
+    .add_systems(Startup, sync_physics_timestep)
+    .add_systems(
+        OnTransition {
+            from: GameState::Loading,
+            to: GameState::InGame,
+        },
+        setup,
+    );
+}
+
+/// advance rapier by the fixed timestep on every FixedUpdate tick, so physics and gameplay advance
+/// in lockstep regardless of the render frame rate. The tick is split into substeps of
+/// [`PHYSICS_STEPS_PER_SECOND`] so bodies move the same whatever the rate of the fixed schedule.
+pub(crate) fn sync_physics_timestep(
+    fixed_time: Res<Time<Fixed>>,
+    mut config: ResMut<RapierConfiguration>,
+) {
+    let dt = fixed_time.timestep().as_secs_f64();
+    config.timestep_mode = TimestepMode::Fixed {
+        dt: dt as f32,
+        substeps: ((dt * PHYSICS_STEPS_PER_SECOND).round() as usize).max(1),
+    };
+}
+
+fn spawn_camera(mut commands: Commands) {
+    // 2D orthographic camera, it lives for the whole session and is not part of a level
+    commands.spawn(Camera2dBundle::default());
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ships: Res<ShipDefinitions>,
+    level: Res<LevelDefinition>,
+    mode: Res<GameMode>,
+) {
+    // player controlled ship
+    player::spawn_player(&mut commands, &asset_server, &ships, &level);
+
+    cargo::spawn_level_cargo(&mut commands, &level);
+
+    // enemies come from the spawners placed in the level, time trials and races are without them
+    if *mode != GameMode::Campaign {
+        return;
+    }
+    for spawner in &level.spawners {
+        spawn_spawner(&mut commands, spawner);
+    }
+}
@@ -0,0 +1,34 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::level::Terrain;
+
+/// line of sight queries against the level terrain, using the rapier query pipeline. Only
+/// terrain colliders block the view, ships, enemies and sensors are ignored.
+#[derive(SystemParam)]
+pub struct LineOfSight<'w, 's> {
+    context: Res<'w, RapierContext>,
+    terrain: Query<'w, 's, (), With<Terrain>>,
+}
+
+impl LineOfSight<'_, '_> {
+    /// whether the straight line between the two points is free of terrain
+    pub fn is_clear(&self, from: Vec2, to: Vec2) -> bool {
+        self.first_terrain_hit(from, to).is_none()
+    }
+
+    /// distance from `from` to the first terrain hit on the way to `to`, `None` if nothing is hit
+    pub fn first_terrain_hit(&self, from: Vec2, to: Vec2) -> Option<f32> {
+        let direction = to - from;
+        let length = direction.length();
+        if length <= f32::EPSILON {
+            return None;
+        }
+        let is_terrain = |entity| self.terrain.contains(entity);
+        let filter = QueryFilter::new().exclude_sensors().predicate(&is_terrain);
+        self.context
+            .cast_ray(from, direction / length, length, true, filter)
+            .map(|(_, distance)| distance)
+    }
+}
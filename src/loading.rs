@@ -0,0 +1,254 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_rapier2d::prelude::*;
+
+use crate::level::{spawn_terrain_run, terrain_runs, LevelDefinition, LevelMap, TerrainRun};
+use crate::navigation::{NavGrid, NAV_AGENT_RADIUS, NAV_CELL_SIZE};
+use crate::state::GameState;
+
+/// level loaded when a game starts, unless something picks another [`CurrentLevel`]
+const LEVEL_PATH: &str = "assets/levels/testworld.ron";
+
+/// terrain rows spawned per frame once the level is prepared, keeps the loading screen responsive
+const ROWS_PER_FRAME: u32 = 64;
+
+pub struct LoadingPlugin;
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadingProgress>()
+            .init_resource::<CurrentLevel>()
+            .add_systems(OnEnter(GameState::Loading), start_level_loading)
+            .add_systems(
+                Update,
+                level_loading_system.run_if(in_state(GameState::Loading)),
+            );
+    }
+}
+
+/// path of the level definition the next game loads
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct CurrentLevel(pub String);
+
+impl Default for CurrentLevel {
+    fn default() -> Self {
+        Self(LEVEL_PATH.to_string())
+    }
+}
+
+impl CurrentLevel {
+    /// file name of the level definition without extension, names per level files like ghosts
+    pub fn name(&self) -> &str {
+        Path::new(&self.0)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("level")
+    }
+}
+
+/// steps of building a level, in order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoadingStage {
+    /// reading the level file and decoding the level image
+    #[default]
+    Decode,
+    /// splitting the pixel rows into runs of equal terrain
+    Segmentation,
+    /// finding the runs on the outline of the terrain, runs inside it need no collider
+    OutlineTracing,
+    /// creating the rapier colliders of the outline runs
+    ColliderBuild,
+    /// building the navigation grid for the enemies
+    Navigation,
+    /// spawning the terrain entities, happens on the main thread
+    Spawning,
+}
+
+impl LoadingStage {
+    pub fn label(self) -> &'static str {
+        match self {
+            LoadingStage::Decode => "Decoding level",
+            LoadingStage::Segmentation => "Segmenting terrain",
+            LoadingStage::OutlineTracing => "Tracing terrain outlines",
+            LoadingStage::ColliderBuild => "Building colliders",
+            LoadingStage::Navigation => "Building navigation grid",
+            LoadingStage::Spawning => "Spawning terrain",
+        }
+    }
+}
+
+/// progress of the level that is currently loading, for the loading screen
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct LoadingProgress {
+    pub stage: LoadingStage,
+    /// progress within the stage, 0.0 to 1.0
+    pub fraction: f32,
+}
+
+/// level data prepared off the main thread, everything but the entities
+struct PreparedLevel {
+    definition: LevelDefinition,
+    map: LevelMap,
+    nav_grid: NavGrid,
+    rows: Vec<Vec<(TerrainRun, Option<Collider>)>>,
+}
+
+/// why the last level could not be loaded, shown in the main menu
+#[derive(Resource, Clone, Debug)]
+pub struct LoadingError(pub String);
+
+/// state of the level that is currently loading
+#[derive(Resource)]
+struct LevelLoader {
+    /// written by the loading task, mirrored into [`LoadingProgress`] every frame
+    progress: Arc<Mutex<LoadingProgress>>,
+    task: Option<Task<Result<PreparedLevel, String>>>,
+    prepared: Option<PreparedLevel>,
+    /// next row to spawn once the level is prepared
+    next_row: u32,
+}
+
+pub(crate) fn start_level_loading(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    mut progress: ResMut<LoadingProgress>,
+) {
+    *progress = LoadingProgress::default();
+    commands.remove_resource::<LoadingError>();
+    let shared_progress = Arc::new(Mutex::new(LoadingProgress::default()));
+    let task_progress = shared_progress.clone();
+
+    let path = level.0.clone();
+    let task =
+        AsyncComputeTaskPool::get().spawn(async move { prepare_level(&path, &task_progress) });
+
+    commands.insert_resource(LevelLoader {
+        progress: shared_progress,
+        task: Some(task),
+        prepared: None,
+        next_row: 0,
+    });
+}
+
+/// decode the level image and build the terrain data. Runs on the async compute pool, errors are
+/// returned to the main thread instead of taking the game down from a worker.
+fn prepare_level(path: &str, progress: &Mutex<LoadingProgress>) -> Result<PreparedLevel, String> {
+    let report = |stage, fraction| {
+        *progress.lock().unwrap() = LoadingProgress { stage, fraction };
+    };
+
+    report(LoadingStage::Decode, 0.0);
+    let definition = LevelDefinition::load(path)?;
+    let level = image::open(&definition.image)
+        .map_err(|err| format!("could not open level image {}: {err}", definition.image))?
+        .flipv();
+    let map = LevelMap::from_image(&level);
+    report(LoadingStage::Decode, 1.0);
+
+    let height = map.height();
+    let mut runs = Vec::with_capacity(height as usize);
+    for y in 0..height {
+        runs.push(terrain_runs(&map, y));
+        report(LoadingStage::Segmentation, (y + 1) as f32 / height as f32);
+    }
+
+    let mut outlines = Vec::with_capacity(height as usize);
+    for (y, row) in runs.iter().enumerate() {
+        let on_outline: Vec<bool> = row
+            .iter()
+            .map(|run| run.is_on_outline(&map, y as u32))
+            .collect();
+        outlines.push(on_outline);
+        report(LoadingStage::OutlineTracing, (y + 1) as f32 / height as f32);
+    }
+
+    let mut rows = Vec::with_capacity(height as usize);
+    for (y, (row, on_outline)) in runs.into_iter().zip(outlines).enumerate() {
+        rows.push(
+            row.into_iter()
+                .zip(on_outline)
+                .map(|(run, on_outline)| {
+                    let collider = on_outline.then(|| run.collider());
+                    (run, collider)
+                })
+                .collect(),
+        );
+        report(LoadingStage::ColliderBuild, (y + 1) as f32 / height as f32);
+    }
+
+    report(LoadingStage::Navigation, 0.0);
+    let nav_grid = NavGrid::from_level(&map, NAV_CELL_SIZE, NAV_AGENT_RADIUS);
+    report(LoadingStage::Navigation, 1.0);
+
+    Ok(PreparedLevel {
+        definition,
+        map,
+        nav_grid,
+        rows,
+    })
+}
+
+/// poll the loading task, then spawn the prepared terrain over several frames and start the
+/// game once everything is in place
+fn level_loading_system(
+    mut commands: Commands,
+    loader: Option<ResMut<LevelLoader>>,
+    mut progress: ResMut<LoadingProgress>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(mut loader) = loader else {
+        return;
+    };
+    let loader = &mut *loader;
+
+    if let Some(task) = loader.task.take() {
+        if !task.is_finished() {
+            loader.task = Some(task);
+            let current = *loader.progress.lock().unwrap();
+            if *progress != current {
+                *progress = current;
+            }
+            return;
+        }
+        // the task is done, so this does not block
+        match block_on(task) {
+            Ok(prepared) => loader.prepared = Some(prepared),
+            Err(err) => {
+                error!("{err}");
+                commands.insert_resource(LoadingError(err));
+                commands.remove_resource::<LevelLoader>();
+                next_state.set(GameState::MainMenu);
+                return;
+            }
+        }
+    }
+
+    let Some(prepared) = loader.prepared.as_mut() else {
+        return;
+    };
+    let height = prepared.map.height();
+    let last_row = (loader.next_row + ROWS_PER_FRAME).min(height);
+    for y in loader.next_row..last_row {
+        let entities = std::mem::take(&mut prepared.rows[y as usize])
+            .into_iter()
+            .map(|(run, collider)| spawn_terrain_run(&mut commands, y, &run, collider))
+            .collect();
+        prepared.map.set_row_entities(y, entities);
+    }
+    loader.next_row = last_row;
+    *progress = LoadingProgress {
+        stage: LoadingStage::Spawning,
+        fraction: last_row as f32 / height.max(1) as f32,
+    };
+
+    if last_row >= height {
+        let prepared = loader.prepared.take().unwrap();
+        commands.insert_resource(prepared.definition);
+        commands.insert_resource(prepared.map);
+        commands.insert_resource(prepared.nav_grid);
+        commands.remove_resource::<LevelLoader>();
+        next_state.set(GameState::InGame);
+    }
+}
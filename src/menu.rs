@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+
+use crate::difficulty::Difficulty;
+use crate::loading::{LoadingError, LoadingProgress};
+use crate::state::GameState;
+
+pub struct MenuPlugin;
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
+            .add_systems(
+                OnExit(GameState::MainMenu),
+                despawn_screen::<MainMenuScreen>,
+            )
+            .add_systems(OnEnter(GameState::Loading), spawn_loading_screen)
+            .add_systems(OnExit(GameState::Loading), despawn_screen::<LoadingScreen>)
+            .add_systems(
+                Update,
+                (
+                    difficulty_text_system.run_if(in_state(GameState::MainMenu)),
+                    loading_progress_system.run_if(in_state(GameState::Loading)),
+                ),
+            )
+            .add_systems(OnEnter(GameState::Paused), spawn_pause_screen)
+            .add_systems(OnExit(GameState::Paused), despawn_screen::<PauseScreen>)
+            .add_systems(OnEnter(GameState::GameOver), spawn_game_over_screen)
+            .add_systems(
+                OnExit(GameState::GameOver),
+                despawn_screen::<GameOverScreen>,
+            )
+            .add_systems(
+                OnEnter(GameState::LevelComplete),
+                spawn_level_complete_screen,
+            )
+            .add_systems(
+                OnExit(GameState::LevelComplete),
+                despawn_screen::<LevelCompleteScreen>,
+            );
+    }
+}
+
+/// root node of the main menu
+#[derive(Component)]
+struct MainMenuScreen;
+
+/// text showing the selected difficulty in the main menu
+#[derive(Component)]
+struct DifficultyText;
+
+/// root node of the loading screen
+#[derive(Component)]
+struct LoadingScreen;
+
+/// text showing the current loading stage
+#[derive(Component)]
+struct LoadingStageText;
+
+/// filled part of the loading progress bar
+#[derive(Component)]
+struct LoadingBar;
+
+/// root node of the pause overlay
+#[derive(Component)]
+struct PauseScreen;
+
+/// root node of the game over overlay
+#[derive(Component)]
+struct GameOverScreen;
+
+/// root node of the level complete overlay
+#[derive(Component)]
+struct LevelCompleteScreen;
+
+fn spawn_main_menu(mut commands: Commands, error: Option<Res<LoadingError>>) {
+    let screen = spawn_screen(
+        &mut commands,
+        MainMenuScreen,
+        Color::BLACK,
+        &[
+            ("laughing-rotary-particle", 48.0),
+            ("Enter: start", 24.0),
+            ("T: time trial", 24.0),
+            ("R: race", 24.0),
+            ("1 / 2 / 3: easy / normal / hard", 24.0),
+            ("Esc: quit", 24.0),
+        ],
+    );
+    commands.entity(screen).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::GRAY,
+                    ..default()
+                },
+            ),
+            DifficultyText,
+        ));
+        if let Some(error) = error {
+            parent.spawn(TextBundle::from_section(
+                error.0.clone(),
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::rgb(1.0, 0.4, 0.4),
+                    ..default()
+                },
+            ));
+        }
+    });
+}
+
+fn difficulty_text_system(
+    difficulty: Res<Difficulty>,
+    mut query: Query<&mut Text, With<DifficultyText>>,
+) {
+    let label = format!("Difficulty: {:?}", *difficulty);
+    for mut text in &mut query {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+fn spawn_loading_screen(mut commands: Commands) {
+    let screen = spawn_screen(
+        &mut commands,
+        LoadingScreen,
+        Color::BLACK,
+        &[("Loading...", 32.0)],
+    );
+    commands.entity(screen).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::GRAY,
+                    ..default()
+                },
+            ),
+            LoadingStageText,
+        ));
+        // progress bar: a dark frame with a growing bright fill
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    width: Val::Px(400.0),
+                    height: Val::Px(16.0),
+                    ..default()
+                },
+                background_color: Color::DARK_GRAY.into(),
+                ..default()
+            })
+            .with_children(|bar| {
+                bar.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: Color::WHITE.into(),
+                        ..default()
+                    },
+                    LoadingBar,
+                ));
+            });
+    });
+}
+
+fn loading_progress_system(
+    progress: Res<LoadingProgress>,
+    mut text_query: Query<&mut Text, With<LoadingStageText>>,
+    mut bar_query: Query<&mut Style, With<LoadingBar>>,
+) {
+    if !progress.is_changed() {
+        return;
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = format!(
+            "{} ({:.0}%)",
+            progress.stage.label(),
+            progress.fraction * 100.0
+        );
+    }
+    for mut style in &mut bar_query {
+        style.width = Val::Percent(progress.fraction * 100.0);
+    }
+}
+
+fn spawn_pause_screen(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        PauseScreen,
+        Color::rgba(0.0, 0.0, 0.0, 0.6),
+        &[
+            ("Paused", 48.0),
+            ("Esc / P: resume", 24.0),
+            ("Q: main menu", 24.0),
+        ],
+    );
+}
+
+fn spawn_game_over_screen(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        GameOverScreen,
+        Color::rgba(0.3, 0.0, 0.0, 0.6),
+        &[
+            ("Game over", 48.0),
+            ("Enter: restart", 24.0),
+            ("Esc: main menu", 24.0),
+        ],
+    );
+}
+
+fn spawn_level_complete_screen(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        LevelCompleteScreen,
+        Color::rgba(0.0, 0.2, 0.1, 0.6),
+        &[
+            ("Level complete", 48.0),
+            ("Enter: restart", 24.0),
+            ("Esc: main menu", 24.0),
+        ],
+    );
+}
+
+/// spawn a full screen node with centered lines of text, `marker` is put on the root node so the
+/// screen can be removed again with [`despawn_screen`]
+pub(crate) fn spawn_screen(
+    commands: &mut Commands,
+    marker: impl Component,
+    background: Color,
+    lines: &[(&str, f32)],
+) -> Entity {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(12.0),
+                    ..default()
+                },
+                background_color: background.into(),
+                ..default()
+            },
+            marker,
+        ))
+        .with_children(|parent| {
+            for &(text, font_size) in lines {
+                parent.spawn(TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ));
+            }
+        })
+        .id()
+}
+
+pub(crate) fn despawn_screen<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,422 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+
+use crate::cargo::{CargoGoal, CargoPod};
+use crate::fog::{AreaExplored, ExploredMask};
+use crate::health::{Health, Team};
+use crate::level::{AreaDefinition, LevelDefinition, LevelMap, TerrainChanged};
+use crate::player::Player;
+use crate::state::{GameState, GameplaySet};
+use crate::time_trial::FinishMarker;
+
+/// minimap pixels along the longer side of the level
+const MINIMAP_RESOLUTION: u32 = 160;
+
+/// share of terrain pixels from which a minimap pixel shows terrain
+const SOLID_SHARE: f32 = 0.25;
+
+/// edge length of the icons in UI pixels
+const ICON_SIZE: f32 = 4.0;
+
+const EMPTY_COLOR: [u8; 4] = [0, 0, 0, 180];
+const HIDDEN_COLOR: [u8; 4] = [24, 24, 32, 230];
+
+pub struct MinimapPlugin;
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MinimapSettings>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::Loading,
+                    to: GameState::InGame,
+                },
+                spawn_minimap,
+            )
+            .add_systems(OnEnter(GameState::Loading), despawn_minimap)
+            .add_systems(OnEnter(GameState::MainMenu), despawn_minimap)
+            .add_systems(
+                Update,
+                (
+                    minimap_terrain_system,
+                    minimap_explored_system,
+                    spawn_minimap_icons_system,
+                    minimap_icon_system,
+                )
+                    .chain()
+                    .run_if(resource_exists::<Minimap>())
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// how the minimap is drawn
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct MinimapSettings {
+    /// only show terrain and icons in areas the players saw, read when the minimap of a level is
+    /// built. Levels with fog always hide their unexplored parts.
+    pub explored_only: bool,
+}
+
+/// the minimap texture of the current level. Every minimap pixel covers a square of
+/// `cell_size` level pixels.
+#[derive(Resource)]
+pub struct Minimap {
+    image: Handle<Image>,
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    /// size of the level in pixels
+    level_size: Vec2,
+    /// hide what the players did not see yet, see [`ExploredMask`]
+    explored_only: bool,
+}
+
+/// root node of the minimap, the image of the terrain
+#[derive(Component)]
+struct MinimapNode;
+
+/// what an icon on the minimap stands for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IconKind {
+    Player,
+    Enemy,
+    Pickup,
+    Objective,
+}
+
+impl IconKind {
+    fn color(self) -> Color {
+        match self {
+            IconKind::Player => Color::WHITE,
+            IconKind::Enemy => Color::rgb(1.0, 0.2, 0.2),
+            IconKind::Pickup => Color::rgb(0.9, 0.7, 0.2),
+            IconKind::Objective => Color::rgb(0.2, 1.0, 0.4),
+        }
+    }
+}
+
+/// icon on the minimap, following an entity or marking a fixed place
+#[derive(Component)]
+struct MinimapIcon {
+    kind: IconKind,
+    target: Option<Entity>,
+}
+
+/// colour of the minimap pixel covering the cell, the average colour of its terrain
+fn cell_color(level: &LevelMap, cell_size: u32, x: u32, y: u32) -> [u8; 4] {
+    let mut sum = [0u32; 3];
+    let mut solid = 0;
+    for py in y * cell_size..(y + 1) * cell_size {
+        for px in x * cell_size..(x + 1) * cell_size {
+            let (px, py) = (px as i32, py as i32);
+            if !level.is_solid_pixel(px, py) {
+                continue;
+            }
+            let Some(pixel) = level.pixel(px, py) else {
+                continue;
+            };
+            for (sum, channel) in sum.iter_mut().zip(pixel.0) {
+                *sum += channel as u32;
+            }
+            solid += 1;
+        }
+    }
+    if (solid as f32) < (cell_size * cell_size) as f32 * SOLID_SHARE {
+        return EMPTY_COLOR;
+    }
+    let [r, g, b] = sum.map(|sum| (sum / solid) as u8);
+    [r, g, b, 255]
+}
+
+/// write the minimap pixels in the range (inclusive) from the level. Without an explored mask
+/// everything counts as unexplored.
+fn paint_cells(
+    minimap: &Minimap,
+    explored: Option<&ExploredMask>,
+    level: &LevelMap,
+    image: &mut Image,
+    min: UVec2,
+    max: UVec2,
+) {
+    let max = max.min(UVec2::new(minimap.width, minimap.height) - 1);
+    let cell_size = minimap.cell_size as i32;
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            let cell_min = IVec2::new(x as i32, y as i32) * cell_size;
+            let hidden = minimap.explored_only
+                && !explored.is_some_and(|explored| {
+                    explored.is_area_explored(cell_min, cell_min + cell_size - 1)
+                });
+            let color = if hidden {
+                HIDDEN_COLOR
+            } else {
+                cell_color(level, minimap.cell_size, x, y)
+            };
+            // the level is flipped, the first image row is the top of the level
+            let row = minimap.height - 1 - y;
+            let offset = ((row * minimap.width + x) * 4) as usize;
+            image.data[offset..offset + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+/// build the minimap texture from the level and show it in the top right corner, with icons for
+/// the areas the objectives send the player to
+fn spawn_minimap(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<MinimapSettings>,
+    level: Res<LevelMap>,
+    definition: Res<LevelDefinition>,
+) {
+    let cell_size = level
+        .width()
+        .max(level.height())
+        .div_ceil(MINIMAP_RESOLUTION)
+        .max(1);
+    let (width, height) = (
+        level.width().div_ceil(cell_size),
+        level.height().div_ceil(cell_size),
+    );
+    let mut image = Image::new_fill(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &EMPTY_COLOR,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    image.sampler = ImageSampler::nearest();
+
+    let mut minimap = Minimap {
+        image: Handle::default(),
+        width,
+        height,
+        cell_size,
+        level_size: Vec2::new(level.width() as f32, level.height() as f32),
+        explored_only: settings.explored_only || definition.fog.is_some(),
+    };
+    // the explored mask of the level is not there yet, it is painted in once it is
+    paint_cells(
+        &minimap,
+        None,
+        &level,
+        &mut image,
+        UVec2::ZERO,
+        UVec2::new(width, height) - 1,
+    );
+    minimap.image = images.add(image);
+
+    let areas: Vec<AreaDefinition> = definition
+        .objective
+        .iter()
+        .flat_map(|objective| objective.areas())
+        .collect();
+    commands
+        .spawn((
+            ImageBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(12.0),
+                    right: Val::Px(12.0),
+                    width: Val::Px(width as f32),
+                    height: Val::Px(height as f32),
+                    ..default()
+                },
+                image: UiImage::new(minimap.image.clone()),
+                ..default()
+            },
+            MinimapNode,
+        ))
+        .with_children(|parent| {
+            for area in areas {
+                let style = icon_style(&minimap, Vec2::from(area.position));
+                spawn_icon(parent, IconKind::Objective, None, style);
+            }
+        });
+    commands.insert_resource(minimap);
+}
+
+fn despawn_minimap(mut commands: Commands, query: Query<Entity, With<MinimapNode>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<Minimap>();
+}
+
+fn spawn_icon(parent: &mut ChildBuilder, kind: IconKind, target: Option<Entity>, style: Style) {
+    parent.spawn((
+        NodeBundle {
+            style,
+            background_color: kind.color().into(),
+            ..default()
+        },
+        MinimapIcon { kind, target },
+    ));
+}
+
+/// style placing an icon centered on the world position
+fn icon_style(minimap: &Minimap, position: Vec2) -> Style {
+    let relative = position / minimap.level_size;
+    Style {
+        position_type: PositionType::Absolute,
+        left: Val::Percent(relative.x * 100.0),
+        bottom: Val::Percent(relative.y * 100.0),
+        width: Val::Px(ICON_SIZE),
+        height: Val::Px(ICON_SIZE),
+        margin: UiRect {
+            left: Val::Px(-ICON_SIZE / 2.0),
+            bottom: Val::Px(-ICON_SIZE / 2.0),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+/// repaint the parts of the minimap where the terrain was carved or settled
+fn minimap_terrain_system(
+    minimap: Res<Minimap>,
+    explored: Option<Res<ExploredMask>>,
+    level: Option<Res<LevelMap>>,
+    mut images: ResMut<Assets<Image>>,
+    mut changes: EventReader<TerrainChanged>,
+) {
+    let (Some(level), Some(image)) = (level, images.get_mut(&minimap.image)) else {
+        changes.clear();
+        return;
+    };
+    let cell_size = minimap.cell_size as i32;
+    for change in changes.read() {
+        let min = change.min.max(IVec2::ZERO) / cell_size;
+        let max = change.max.max(IVec2::ZERO) / cell_size;
+        paint_cells(
+            &minimap,
+            explored.as_deref(),
+            &level,
+            image,
+            min.as_uvec2(),
+            max.as_uvec2(),
+        );
+    }
+}
+
+/// uncover the parts of a hidden minimap the players saw
+fn minimap_explored_system(
+    minimap: Res<Minimap>,
+    explored: Option<Res<ExploredMask>>,
+    level: Option<Res<LevelMap>>,
+    mut images: ResMut<Assets<Image>>,
+    mut events: EventReader<AreaExplored>,
+) {
+    let (Some(explored), Some(level), Some(image)) =
+        (explored, level, images.get_mut(&minimap.image))
+    else {
+        events.clear();
+        return;
+    };
+    if !minimap.explored_only {
+        events.clear();
+        return;
+    }
+    let cell_size = minimap.cell_size as i32;
+    if explored.is_added() {
+        // a new level, possibly with the exploration of an earlier run
+        events.clear();
+        let max = UVec2::new(minimap.width, minimap.height) - 1;
+        paint_cells(&minimap, Some(&explored), &level, image, UVec2::ZERO, max);
+        return;
+    }
+    for event in events.read() {
+        let min = event.min.max(IVec2::ZERO) / cell_size;
+        let max = event.max.max(IVec2::ZERO) / cell_size;
+        paint_cells(
+            &minimap,
+            Some(&explored),
+            &level,
+            image,
+            min.as_uvec2(),
+            max.as_uvec2(),
+        );
+    }
+}
+
+/// give new players, enemies, pods and objective markers an icon
+fn spawn_minimap_icons_system(
+    mut commands: Commands,
+    minimap: Res<Minimap>,
+    nodes: Query<Entity, With<MinimapNode>>,
+    players: Query<(Entity, &Transform), Added<Player>>,
+    enemies: Query<(Entity, &Transform, &Team), (Added<Team>, With<Health>)>,
+    pods: Query<(Entity, &Transform), Added<CargoPod>>,
+    objectives: Query<(Entity, &Transform), Or<(Added<CargoGoal>, Added<FinishMarker>)>>,
+) {
+    let Ok(node) = nodes.get_single() else {
+        return;
+    };
+    let enemies = enemies
+        .iter()
+        .filter(|(_, _, team)| **team == Team::Enemy)
+        .map(|(entity, transform, _)| (IconKind::Enemy, entity, transform));
+    let icons = players
+        .iter()
+        .map(|(entity, transform)| (IconKind::Player, entity, transform))
+        .chain(enemies)
+        .chain(
+            pods.iter()
+                .map(|(entity, transform)| (IconKind::Pickup, entity, transform)),
+        )
+        .chain(
+            objectives
+                .iter()
+                .map(|(entity, transform)| (IconKind::Objective, entity, transform)),
+        );
+    commands.entity(node).with_children(|parent| {
+        for (kind, target, transform) in icons {
+            let style = icon_style(&minimap, transform.translation.xy());
+            spawn_icon(parent, kind, Some(target), style);
+        }
+    });
+}
+
+/// move the icons along with their entities, remove the icons of entities that are gone and hide
+/// unexplored ones
+fn minimap_icon_system(
+    mut commands: Commands,
+    minimap: Res<Minimap>,
+    explored: Option<Res<ExploredMask>>,
+    targets: Query<Ref<Transform>>,
+    mut icons: Query<(Entity, &MinimapIcon, &mut Style, &mut Visibility)>,
+) {
+    for (entity, icon, mut style, mut visibility) in &mut icons {
+        let Some(target) = icon.target else {
+            continue;
+        };
+        let Ok(transform) = targets.get(target) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        let position = transform.translation.xy();
+        if transform.is_changed() {
+            let relative = position / minimap.level_size;
+            style.left = Val::Percent(relative.x * 100.0);
+            style.bottom = Val::Percent(relative.y * 100.0);
+        }
+        // the players always know where they are
+        let visible = !minimap.explored_only
+            || icon.kind == IconKind::Player
+            || explored
+                .as_ref()
+                .is_some_and(|explored| explored.is_explored(position));
+        let new_visibility = if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+    }
+}
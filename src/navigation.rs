@@ -0,0 +1,420 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use bevy::prelude::*;
+
+use crate::debris::settle_debris_system;
+use crate::level::{carve_terrain_system, LevelMap, TerrainChanged};
+use crate::state::GameplaySet;
+
+/// edge length of a navigation cell in pixels
+pub const NAV_CELL_SIZE: u32 = 4;
+
+/// radius in pixels of the largest agent using the grid, terrain is inflated by it
+pub const NAV_AGENT_RADIUS: f32 = 8.0;
+
+/// cost of a straight step between cells, diagonal steps cost [`DIAGONAL_COST`]
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+pub struct NavigationPlugin;
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        // terrain changes are read in the tick they happen, events only live for two frames and
+        // there may be no tick in between
+        app.add_systems(
+            FixedUpdate,
+            update_nav_grid_system
+                .after(carve_terrain_system)
+                .after(settle_debris_system)
+                .in_set(GameplaySet),
+        );
+    }
+}
+
+/// coarse grid over the level telling where agents can fly, built from the [`LevelMap`]. A cell
+/// is walkable when it is empty and no terrain is closer than the agent radius.
+#[derive(Resource, Clone)]
+pub struct NavGrid {
+    /// size of the grid in cells
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    /// how many cells around a solid cell are not walkable
+    clearance: i32,
+    /// cells containing at least one terrain pixel
+    solid: Vec<bool>,
+    walkable: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn from_level(level: &LevelMap, cell_size: u32, agent_radius: f32) -> Self {
+        let width = level.width().div_ceil(cell_size);
+        let height = level.height().div_ceil(cell_size);
+        let mut grid = Self {
+            width,
+            height,
+            cell_size,
+            clearance: (agent_radius / cell_size as f32).ceil() as i32,
+            solid: vec![false; (width * height) as usize],
+            walkable: vec![false; (width * height) as usize],
+        };
+        grid.update_cells(
+            level,
+            IVec2::ZERO,
+            IVec2::new(width as i32, height as i32) - 1,
+        );
+        grid
+    }
+
+    /// recompute the cells covering the given pixel area after the terrain changed there
+    pub fn update_region(&mut self, level: &LevelMap, min: IVec2, max: IVec2) {
+        let cell_size = self.cell_size as i32;
+        self.update_cells(
+            level,
+            min.div_euclid(IVec2::splat(cell_size)),
+            max.div_euclid(IVec2::splat(cell_size)),
+        );
+    }
+
+    /// recompute solid cells in the range (inclusive) and walkable cells around it
+    fn update_cells(&mut self, level: &LevelMap, min: IVec2, max: IVec2) {
+        let grid_max = IVec2::new(self.width as i32, self.height as i32) - 1;
+        let cell_size = self.cell_size as i32;
+
+        let (min, max) = (min.max(IVec2::ZERO), max.min(grid_max));
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let solid = (0..cell_size).any(|py| {
+                    (0..cell_size)
+                        .any(|px| level.is_solid_pixel(x * cell_size + px, y * cell_size + py))
+                });
+                let index = self.index(IVec2::new(x, y));
+                self.solid[index] = solid;
+            }
+        }
+
+        // solid cells make their surroundings unwalkable, so the inflated border changes too
+        let (min, max) = (
+            (min - self.clearance).max(IVec2::ZERO),
+            (max + self.clearance).min(grid_max),
+        );
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let cell = IVec2::new(x, y);
+                let walkable = !self.is_near_solid(cell);
+                let index = self.index(cell);
+                self.walkable[index] = walkable;
+            }
+        }
+    }
+
+    fn is_near_solid(&self, cell: IVec2) -> bool {
+        let clearance = self.clearance;
+        (-clearance..=clearance).any(|dy| {
+            (-clearance..=clearance).any(|dx| {
+                dx * dx + dy * dy <= clearance * clearance
+                    && self
+                        .index_checked(cell + IVec2::new(dx, dy))
+                        .is_some_and(|index| self.solid[index])
+            })
+        })
+    }
+
+    fn index(&self, cell: IVec2) -> usize {
+        (cell.y as u32 * self.width + cell.x as u32) as usize
+    }
+
+    fn index_checked(&self, cell: IVec2) -> Option<usize> {
+        (cell.x >= 0 && cell.y >= 0 && cell.x < self.width as i32 && cell.y < self.height as i32)
+            .then(|| self.index(cell))
+    }
+
+    /// cell containing the world position
+    pub fn cell_at(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size as f32).floor().as_ivec2()
+    }
+
+    /// world position of the center of a cell
+    pub fn cell_center(&self, cell: IVec2) -> Vec2 {
+        (cell.as_vec2() + 0.5) * self.cell_size as f32
+    }
+
+    /// whether an agent can be in the cell, everything outside the grid is not walkable
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        self.index_checked(cell)
+            .is_some_and(|index| self.walkable[index])
+    }
+
+    /// whether an agent can fly straight from one point to the other
+    pub fn is_line_walkable(&self, from: Vec2, to: Vec2) -> bool {
+        // sample twice per cell so no cell the line crosses is skipped over
+        let step = self.cell_size as f32 / 2.0;
+        let samples = (from.distance(to) / step).ceil() as u32;
+        (0..=samples).all(|sample| {
+            let t = sample as f32 / samples.max(1) as f32;
+            self.is_walkable(self.cell_at(from.lerp(to, t)))
+        })
+    }
+
+    /// closest walkable cell, agents hugging a wall are inside the inflated border
+    fn nearest_walkable(&self, cell: IVec2) -> Option<IVec2> {
+        let search_radius = self.clearance + 2;
+        (0..=search_radius).find_map(|radius| {
+            (-radius..=radius)
+                .flat_map(|dy| (-radius..=radius).map(move |dx| IVec2::new(dx, dy)))
+                .filter(|offset| offset.x.abs() == radius || offset.y.abs() == radius)
+                .map(|offset| cell + offset)
+                .find(|&cell| self.is_walkable(cell))
+        })
+    }
+
+    /// shortest path between two world positions as a list of points to fly through, starting at
+    /// `start`. `None` if either end is far inside terrain or the goal can not be reached.
+    pub fn find_path(&self, start: Vec2, goal: Vec2) -> Option<Vec<Vec2>> {
+        let start_cell = self.nearest_walkable(self.cell_at(start))?;
+        let goal_cell = self.nearest_walkable(self.cell_at(goal))?;
+        let cells = self.find_cell_path(start_cell, goal_cell)?;
+
+        let mut path: Vec<Vec2> = cells.iter().map(|&cell| self.cell_center(cell)).collect();
+        // start and goal in the same cell, the path still has to lead from one to the other
+        if path.len() == 1 {
+            path.push(path[0]);
+        }
+        path[0] = start;
+        if self.is_walkable(self.cell_at(goal)) {
+            *path.last_mut().unwrap() = goal;
+        }
+        Some(self.smooth_path(&path))
+    }
+
+    /// A* over the walkable cells, moving in 8 directions without cutting corners
+    fn find_cell_path(&self, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+        let heuristic = |cell: IVec2| {
+            let delta = (goal - cell).abs();
+            let (min, max) = (delta.min_element() as u32, delta.max_element() as u32);
+            STRAIGHT_COST * max + (DIAGONAL_COST - STRAIGHT_COST) * min
+        };
+
+        let cell_count = (self.width * self.height) as usize;
+        let mut cost = vec![u32::MAX; cell_count];
+        let mut came_from = vec![usize::MAX; cell_count];
+        let mut open = BinaryHeap::new();
+
+        let start_index = self.index(start);
+        let goal_index = self.index(goal);
+        cost[start_index] = 0;
+        open.push(Reverse((heuristic(start), start_index)));
+
+        while let Some(Reverse((_, index))) = open.pop() {
+            if index == goal_index {
+                let mut path = vec![goal];
+                let mut current = index;
+                while current != start_index {
+                    current = came_from[current];
+                    let cell = IVec2::new(
+                        (current as u32 % self.width) as i32,
+                        (current as u32 / self.width) as i32,
+                    );
+                    path.push(cell);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let cell = IVec2::new(
+                (index as u32 % self.width) as i32,
+                (index as u32 / self.width) as i32,
+            );
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if dx == 0 && dy == 0 {
+                        continue;
+                    }
+                    let neighbour = cell + IVec2::new(dx, dy);
+                    if !self.is_walkable(neighbour) {
+                        continue;
+                    }
+                    let diagonal = dx != 0 && dy != 0;
+                    if diagonal
+                        && !(self.is_walkable(cell + IVec2::new(dx, 0))
+                            && self.is_walkable(cell + IVec2::new(0, dy)))
+                    {
+                        continue;
+                    }
+                    let step = if diagonal {
+                        DIAGONAL_COST
+                    } else {
+                        STRAIGHT_COST
+                    };
+                    let neighbour_cost = cost[index] + step;
+                    let neighbour_index = self.index(neighbour);
+                    if neighbour_cost < cost[neighbour_index] {
+                        cost[neighbour_index] = neighbour_cost;
+                        came_from[neighbour_index] = index;
+                        open.push(Reverse((
+                            neighbour_cost + heuristic(neighbour),
+                            neighbour_index,
+                        )));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// drop every point that can be skipped by flying straight to a later one
+    fn smooth_path(&self, path: &[Vec2]) -> Vec<Vec2> {
+        let Some((&last, _)) = path.split_last() else {
+            return Vec::new();
+        };
+        let mut smoothed = vec![path[0]];
+        let mut anchor = 0;
+        for index in 2..path.len() {
+            if !self.is_line_walkable(path[anchor], path[index]) {
+                anchor = index - 1;
+                smoothed.push(path[anchor]);
+            }
+        }
+        if path.len() > 1 {
+            smoothed.push(last);
+        }
+        smoothed
+    }
+}
+
+/// keep the navigation grid in sync with carved and settled terrain
+fn update_nav_grid_system(
+    level: Option<Res<LevelMap>>,
+    nav_grid: Option<ResMut<NavGrid>>,
+    mut changes: EventReader<TerrainChanged>,
+) {
+    let (Some(level), Some(mut nav_grid)) = (level, nav_grid) else {
+        changes.clear();
+        return;
+    };
+    for change in changes.read() {
+        nav_grid.update_region(&level, change.min, change.max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    use super::*;
+    use crate::level::EMPTY_SPACE;
+
+    const TERRAIN: Rgba<u8> = Rgba([120, 90, 60, 255]);
+
+    /// a level from rows of pixels, `#` is terrain and the first row is the bottom of the level
+    fn level(rows: &[&str]) -> LevelMap {
+        let width = rows[0].len() as u32;
+        let image = RgbaImage::from_fn(width, rows.len() as u32, |x, y| {
+            match rows[y as usize].as_bytes()[x as usize] {
+                b'#' => TERRAIN,
+                _ => EMPTY_SPACE,
+            }
+        });
+        LevelMap::from_image(&DynamicImage::ImageRgba8(image))
+    }
+
+    /// a wall splitting the level, open at the top
+    const WALL: &[&str] = &[
+        "......#.....",
+        "......#.....",
+        "......#.....",
+        "......#.....",
+        "......#.....",
+        "......#.....",
+        "............",
+        "............",
+    ];
+
+    #[test]
+    fn path_leads_around_terrain() {
+        let grid = NavGrid::from_level(&level(WALL), 1, 0.0);
+        let (start, goal) = (Vec2::new(2.5, 1.5), Vec2::new(9.5, 1.5));
+        let path = grid.find_path(start, goal).unwrap();
+
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.iter().any(|point| point.y >= 6.0));
+        for segment in path.windows(2) {
+            assert!(grid.is_line_walkable(segment[0], segment[1]));
+        }
+    }
+
+    #[test]
+    fn walled_off_goal_is_unreachable() {
+        let mut rows = WALL.to_vec();
+        rows[6] = "......#.....";
+        rows[7] = "......#.....";
+        let grid = NavGrid::from_level(&level(&rows), 1, 0.0);
+
+        assert_eq!(
+            grid.find_path(Vec2::new(2.5, 1.5), Vec2::new(9.5, 1.5)),
+            None
+        );
+    }
+
+    #[test]
+    fn terrain_is_inflated_by_the_agent_radius() {
+        let grid = NavGrid::from_level(
+            &level(&[
+                ".........",
+                ".........",
+                ".........",
+                ".........",
+                "....#....",
+                ".........",
+                ".........",
+                ".........",
+                ".........",
+            ]),
+            1,
+            2.0,
+        );
+
+        assert!(!grid.is_walkable(IVec2::new(4, 4)));
+        assert!(!grid.is_walkable(IVec2::new(6, 4)));
+        assert!(!grid.is_walkable(IVec2::new(4, 2)));
+        assert!(grid.is_walkable(IVec2::new(7, 4)));
+        // the inflation is round, the corners of its square stay walkable
+        assert!(grid.is_walkable(IVec2::new(6, 6)));
+    }
+
+    #[test]
+    fn open_path_is_smoothed_to_a_straight_line() {
+        let grid = NavGrid::from_level(&level(&["............"; 8]), 1, 0.0);
+        let (start, goal) = (Vec2::new(1.5, 1.5), Vec2::new(10.5, 6.5));
+
+        assert_eq!(grid.find_path(start, goal), Some(vec![start, goal]));
+    }
+
+    #[test]
+    fn carved_gap_opens_a_path() {
+        let mut rows = WALL.to_vec();
+        rows[6] = "......#.....";
+        rows[7] = "......#.....";
+        let mut level = level(&rows);
+        let mut grid = NavGrid::from_level(&level, 1, 0.0);
+        let (start, goal) = (Vec2::new(2.5, 1.5), Vec2::new(9.5, 1.5));
+        assert_eq!(grid.find_path(start, goal), None);
+
+        level.set_pixel(6, 7, EMPTY_SPACE);
+        grid.update_region(&level, IVec2::new(6, 7), IVec2::new(6, 7));
+
+        let path = grid.find_path(start, goal).unwrap();
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.iter().any(|point| point.y >= 7.0));
+    }
+
+    #[test]
+    fn start_and_goal_in_the_same_cell() {
+        let grid = NavGrid::from_level(&level(&["........"; 8]), 4, 0.0);
+        let (start, goal) = (Vec2::new(1.0, 1.0), Vec2::new(3.0, 2.5));
+
+        assert_eq!(grid.find_path(start, goal), Some(vec![start, goal]));
+    }
+}
@@ -0,0 +1,360 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::cargo::{cargo_delivery_system, CargoDelivered};
+use crate::enemy::{BossPart, EnemyKind, EnemySpawner, Flyer, Turret};
+use crate::health::{death_system, Health, Team};
+use crate::level::{AreaDefinition, LevelDefinition};
+use crate::player::Player;
+use crate::state::{GameMode, GameState, GameplaySet};
+
+pub struct ObjectivesPlugin;
+impl Plugin for ObjectivesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ObjectiveCompleted>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::Loading,
+                    to: GameState::InGame,
+                },
+                start_objectives.run_if(resource_equals(GameMode::Campaign)),
+            )
+            .add_systems(
+                FixedUpdate,
+                objective_system
+                    .after(cargo_delivery_system)
+                    .after(death_system)
+                    .run_if(resource_exists::<Objectives>())
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// goal of a level, or the condition that loses it. Composites combine other objectives.
+#[derive(Deserialize, Clone, Debug)]
+pub enum Objective {
+    /// every one of the objectives
+    AllOf(Vec<Objective>),
+    /// at least one of the objectives
+    AnyOf(Vec<Objective>),
+    /// no such enemy is left, and the spawners are not going to release any more
+    DestroyAll(Targets),
+    /// this many cargo pods were delivered to the cargo goal
+    CollectCargo(u32),
+    /// the player flew into the area
+    Reach(AreaDefinition),
+    /// the level has been played for this many seconds
+    Survive(f32),
+}
+
+/// enemies a [`Objective::DestroyAll`] is about
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Targets {
+    /// stationary turrets, not the turrets of bosses
+    Turrets,
+    /// every enemy
+    Enemies,
+}
+
+impl Objective {
+    /// short description for the player
+    pub fn label(&self) -> String {
+        match self {
+            Objective::AllOf(_) => "complete all of".to_string(),
+            Objective::AnyOf(_) => "complete one of".to_string(),
+            Objective::DestroyAll(Targets::Turrets) => "destroy all turrets".to_string(),
+            Objective::DestroyAll(Targets::Enemies) => "destroy all enemies".to_string(),
+            Objective::CollectCargo(count) => format!("deliver {count} cargo"),
+            Objective::Reach(_) => "reach the exit".to_string(),
+            Objective::Survive(seconds) => format!("survive {seconds:.0} seconds"),
+        }
+    }
+
+    /// areas the player has to reach for the objective, composites included
+    pub fn areas(&self) -> Vec<AreaDefinition> {
+        match self {
+            Objective::AllOf(children) | Objective::AnyOf(children) => {
+                children.iter().flat_map(Objective::areas).collect()
+            }
+            Objective::Reach(area) => vec![*area],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// an objective was completed, composites included
+#[derive(Event, Clone, Debug)]
+pub struct ObjectiveCompleted {
+    pub label: String,
+}
+
+/// the objective tree of a level while it is played
+pub struct ObjectiveNode {
+    pub objective: Objective,
+    pub children: Vec<ObjectiveNode>,
+    /// objectives stay completed, even if e.g. the player leaves the area again
+    pub complete: bool,
+}
+
+impl ObjectiveNode {
+    fn new(objective: &Objective) -> Self {
+        let children = match objective {
+            Objective::AllOf(children) | Objective::AnyOf(children) => {
+                children.iter().map(ObjectiveNode::new).collect()
+            }
+            _ => Vec::new(),
+        };
+        Self {
+            objective: objective.clone(),
+            children,
+            complete: false,
+        }
+    }
+
+    /// update the completion of the tree, the labels of newly completed objectives are added to
+    /// `completed`
+    fn update(&mut self, facts: &Facts, completed: &mut Vec<String>) -> bool {
+        if self.complete {
+            return true;
+        }
+        // every child is updated, so completions deeper in the tree are reported too
+        let children: Vec<bool> = self
+            .children
+            .iter_mut()
+            .map(|child| child.update(facts, completed))
+            .collect();
+        self.complete = match &self.objective {
+            Objective::AllOf(_) => children.iter().all(|&complete| complete),
+            Objective::AnyOf(_) => children.iter().any(|&complete| complete),
+            Objective::DestroyAll(Targets::Turrets) => facts.turrets_cleared,
+            Objective::DestroyAll(Targets::Enemies) => facts.enemies_cleared,
+            Objective::CollectCargo(count) => facts.cargo_delivered >= *count,
+            Objective::Reach(area) => facts.player_position.is_some_and(|position| {
+                position.distance(Vec2::from(area.position)) <= area.radius
+            }),
+            Objective::Survive(seconds) => facts.elapsed >= *seconds,
+        };
+        if self.complete {
+            completed.push(self.objective.label());
+        }
+        self.complete
+    }
+}
+
+/// state of the level as far as objectives care
+struct Facts {
+    elapsed: f32,
+    cargo_delivered: u32,
+    player_position: Option<Vec2>,
+    turrets_cleared: bool,
+    enemies_cleared: bool,
+}
+
+/// objectives of the level being played
+#[derive(Resource)]
+pub struct Objectives {
+    /// completing it wins the level
+    pub win: Option<ObjectiveNode>,
+    /// completing it loses the level
+    pub fail: Option<ObjectiveNode>,
+    /// seconds played
+    pub elapsed: f32,
+    pub cargo_delivered: u32,
+    /// whether any turret or enemy was alive yet, so a level is not cleared before its
+    /// spawners released anything
+    seen_turrets: bool,
+    seen_enemies: bool,
+}
+
+fn start_objectives(mut commands: Commands, level: Res<LevelDefinition>) {
+    if level.objective.is_none() && level.failure.is_none() {
+        return;
+    }
+    commands.insert_resource(Objectives {
+        win: level.objective.as_ref().map(ObjectiveNode::new),
+        fail: level.failure.as_ref().map(ObjectiveNode::new),
+        elapsed: 0.0,
+        cargo_delivered: 0,
+        seen_turrets: false,
+        seen_enemies: false,
+    });
+}
+
+/// events the objectives count and report
+#[derive(SystemParam)]
+pub(crate) struct ObjectiveEvents<'w, 's> {
+    delivered: EventReader<'w, 's, CargoDelivered>,
+    completed: EventWriter<'w, ObjectiveCompleted>,
+}
+
+/// track the progress of the objectives and end the level once it is won or lost
+pub(crate) fn objective_system(
+    time: Res<Time>,
+    mut objectives: ResMut<Objectives>,
+    player_query: Query<&Transform, With<Player>>,
+    enemies: Query<(&Team, Option<&Turret>, Has<Flyer>, Has<BossPart>), With<Health>>,
+    spawners: Query<&EnemySpawner>,
+    mut events: ObjectiveEvents,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    objectives.elapsed += time.delta_seconds();
+    objectives.cargo_delivered += events.delivered.read().count() as u32;
+
+    let (mut turrets, mut enemy_count) = (0, 0);
+    for (team, turret, is_flyer, is_boss) in &enemies {
+        if *team != Team::Enemy {
+            continue;
+        }
+        enemy_count += 1;
+        if turret.is_some() && !is_flyer && !is_boss {
+            turrets += 1;
+        }
+    }
+    let pending = |is_target: fn(&EnemyKind) -> bool| {
+        spawners
+            .iter()
+            .any(|spawner| spawner.pending_kinds().any(is_target))
+    };
+    objectives.seen_turrets |= turrets > 0;
+    objectives.seen_enemies |= enemy_count > 0;
+
+    let facts = Facts {
+        elapsed: objectives.elapsed,
+        cargo_delivered: objectives.cargo_delivered,
+        player_position: player_query
+            .get_single()
+            .ok()
+            .map(|transform| transform.translation.xy()),
+        turrets_cleared: objectives.seen_turrets && turrets == 0 && !pending(EnemyKind::is_turret),
+        enemies_cleared: objectives.seen_enemies && enemy_count == 0 && !pending(|_| true),
+    };
+
+    let mut completed = Vec::new();
+    let objectives = &mut *objectives;
+    let won = objectives
+        .win
+        .as_mut()
+        .is_some_and(|win| win.update(&facts, &mut completed));
+    for label in completed {
+        info!("objective completed: {label}");
+        events.completed.send(ObjectiveCompleted { label });
+    }
+    let lost = objectives
+        .fail
+        .as_mut()
+        .is_some_and(|fail| fail.update(&facts, &mut Vec::new()));
+
+    if lost {
+        info!("level lost");
+        next_state.set(GameState::GameOver);
+    } else if won {
+        info!("level complete");
+        next_state.set(GameState::LevelComplete);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXIT: AreaDefinition = AreaDefinition {
+        position: (100.0, 100.0),
+        radius: 20.0,
+    };
+
+    fn facts() -> Facts {
+        Facts {
+            elapsed: 0.0,
+            cargo_delivered: 0,
+            player_position: None,
+            turrets_cleared: false,
+            enemies_cleared: false,
+        }
+    }
+
+    #[test]
+    fn all_of_needs_every_child() {
+        let mut node = ObjectiveNode::new(&Objective::AllOf(vec![
+            Objective::CollectCargo(1),
+            Objective::Survive(10.0),
+        ]));
+        let mut completed = Vec::new();
+
+        let delivered = Facts {
+            cargo_delivered: 1,
+            ..facts()
+        };
+        assert!(!node.update(&delivered, &mut completed));
+        assert_eq!(completed, ["deliver 1 cargo"]);
+
+        completed.clear();
+        let survived = Facts {
+            elapsed: 10.0,
+            ..delivered
+        };
+        assert!(node.update(&survived, &mut completed));
+        assert_eq!(completed, ["survive 10 seconds", "complete all of"]);
+    }
+
+    #[test]
+    fn any_of_needs_one_child() {
+        let mut node = ObjectiveNode::new(&Objective::AnyOf(vec![
+            Objective::DestroyAll(Targets::Turrets),
+            Objective::Reach(EXIT),
+        ]));
+        let mut completed = Vec::new();
+
+        assert!(!node.update(&facts(), &mut completed));
+        assert!(completed.is_empty());
+
+        let cleared = Facts {
+            turrets_cleared: true,
+            ..facts()
+        };
+        assert!(node.update(&cleared, &mut completed));
+        assert_eq!(completed, ["destroy all turrets", "complete one of"]);
+    }
+
+    #[test]
+    fn reached_area_stays_complete_after_leaving_it() {
+        let mut node = ObjectiveNode::new(&Objective::AllOf(vec![
+            Objective::Reach(EXIT),
+            Objective::AnyOf(vec![Objective::CollectCargo(2)]),
+        ]));
+        let mut completed = Vec::new();
+
+        let at_exit = Facts {
+            player_position: Some(Vec2::new(110.0, 95.0)),
+            ..facts()
+        };
+        assert!(!node.update(&at_exit, &mut completed));
+        assert_eq!(completed, ["reach the exit"]);
+
+        // the player flew away, or was destroyed, before the cargo was delivered
+        completed.clear();
+        let delivered_elsewhere = Facts {
+            cargo_delivered: 2,
+            player_position: None,
+            ..facts()
+        };
+        assert!(node.update(&delivered_elsewhere, &mut completed));
+        assert_eq!(
+            completed,
+            ["deliver 2 cargo", "complete one of", "complete all of"]
+        );
+    }
+
+    #[test]
+    fn completed_objectives_are_reported_once() {
+        let mut node = ObjectiveNode::new(&Objective::Survive(5.0));
+        let survived = Facts {
+            elapsed: 6.0,
+            ..facts()
+        };
+        let mut completed = Vec::new();
+        assert!(node.update(&survived, &mut completed));
+        assert!(node.update(&facts(), &mut completed));
+        assert_eq!(completed, ["survive 5 seconds"]);
+    }
+}
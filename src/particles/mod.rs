@@ -0,0 +1,346 @@
+use std::ops::Range;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Velocity;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::level::LevelMap;
+use crate::ship::ShipInput;
+use crate::state::GameplaySet;
+
+/// the particle budget. Every particle is its own sprite entity, the sprites share no texture
+/// so the renderer batches their draws, but spawning, extracting and updating still costs per
+/// entity. Emitters and effects stop spawning once this many particles are alive.
+const MAX_PARTICLES: usize = 4096;
+
+pub struct ParticlePlugin;
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ParticleEffect>()
+            .insert_resource(ParticleRng(StdRng::from_entropy()))
+            .add_systems(
+                Update,
+                (
+                    ship_exhaust_system,
+                    emitter_system,
+                    effect_system,
+                    particle_update_system,
+                )
+                    .chain()
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// the random number generator of the particles, kept apart from
+/// [`GameRng`](crate::replay::GameRng). Particles run every rendered frame and only bounce off the
+/// level pixels, nothing in the game reads them back, so they may differ between replays without
+/// the run drifting.
+#[derive(Resource)]
+struct ParticleRng(StdRng);
+
+/// ready made particle setups
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticlePreset {
+    /// continuous flame behind a thrusting ship
+    Exhaust,
+    /// short spark burst where a projectile hits something
+    BulletImpact,
+    /// chunks of rock that fall and bounce off the terrain
+    TerrainDebris,
+    /// large burst when a ship is destroyed
+    Explosion,
+}
+
+impl ParticlePreset {
+    pub fn config(self) -> EmitterConfig {
+        match self {
+            ParticlePreset::Exhaust => EmitterConfig {
+                rate: 120.0,
+                burst: 0,
+                lifetime: 0.15..0.35,
+                speed: 60.0..120.0,
+                spread: 0.35,
+                size: (2.0, 0.5),
+                color: (Color::rgb(1.0, 0.9, 0.4), Color::rgba(0.8, 0.1, 0.0, 0.0)),
+                gravity: 0.0,
+                collides: false,
+            },
+            ParticlePreset::BulletImpact => EmitterConfig {
+                rate: 0.0,
+                burst: 12,
+                lifetime: 0.1..0.3,
+                speed: 40.0..150.0,
+                spread: 1.2,
+                size: (1.5, 0.5),
+                color: (Color::WHITE, Color::rgba(1.0, 0.6, 0.1, 0.0)),
+                gravity: 0.0,
+                collides: false,
+            },
+            ParticlePreset::TerrainDebris => EmitterConfig {
+                rate: 0.0,
+                burst: 16,
+                lifetime: 1.0..2.5,
+                speed: 30.0..90.0,
+                spread: 1.4,
+                size: (1.5, 1.0),
+                color: (Color::GRAY, Color::rgba(0.3, 0.3, 0.3, 0.0)),
+                gravity: 300.0,
+                collides: true,
+            },
+            ParticlePreset::Explosion => EmitterConfig {
+                rate: 0.0,
+                burst: 96,
+                lifetime: 0.4..1.2,
+                speed: 20.0..220.0,
+                spread: std::f32::consts::PI,
+                size: (4.0, 1.0),
+                color: (Color::rgb(1.0, 0.95, 0.6), Color::rgba(0.5, 0.05, 0.0, 0.0)),
+                gravity: 0.0,
+                collides: true,
+            },
+        }
+    }
+}
+
+/// how an emitter creates its particles
+#[derive(Clone, Debug)]
+pub struct EmitterConfig {
+    /// particles per second while the emitter is active
+    pub rate: f32,
+    /// particles spawned at once for one shot effects
+    pub burst: u32,
+    /// lifetime range in seconds
+    pub lifetime: Range<f32>,
+    /// initial speed range in pixels per second
+    pub speed: Range<f32>,
+    /// half angle of the emission cone in radians around the emit direction
+    pub spread: f32,
+    /// sprite size in pixels at birth and at death
+    pub size: (f32, f32),
+    /// colour at birth and at death
+    pub color: (Color, Color),
+    /// downwards acceleration in pixels per second squared
+    pub gravity: f32,
+    /// whether particles bounce off the level terrain
+    pub collides: bool,
+}
+
+/// continuous particle source attached to an entity
+#[derive(Component)]
+pub struct ParticleEmitter {
+    pub config: EmitterConfig,
+    /// emit position relative to the entity
+    pub offset: Vec2,
+    /// emit direction relative to the entity
+    pub direction: Vec2,
+    pub active: bool,
+    /// fractional particles carried over between frames
+    accumulator: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(preset: ParticlePreset, offset: Vec2, direction: Vec2) -> Self {
+        Self {
+            config: preset.config(),
+            offset,
+            direction,
+            active: false,
+            accumulator: 0.0,
+        }
+    }
+}
+
+/// request a one shot burst of particles at a world position
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ParticleEffect {
+    pub preset: ParticlePreset,
+    pub position: Vec2,
+    /// main direction of the burst, the zero vector emits in all directions
+    pub direction: Vec2,
+    /// velocity added to every particle, e.g. of the destroyed ship
+    pub velocity: Vec2,
+}
+
+/// a single live particle
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    size: (f32, f32),
+    color: (Color, Color),
+    gravity: f32,
+    collides: bool,
+}
+
+/// run the exhaust emitters of ships while their main engine pushes forward
+fn ship_exhaust_system(mut query: Query<(&ShipInput, &mut ParticleEmitter)>) {
+    for (input, mut emitter) in &mut query {
+        let active = input.movement_factor > 0.0;
+        if emitter.active != active {
+            emitter.active = active;
+        }
+    }
+}
+
+fn emitter_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rng: ResMut<ParticleRng>,
+    particles: Query<(), With<Particle>>,
+    mut query: Query<(&mut ParticleEmitter, &GlobalTransform, Option<&Velocity>)>,
+) {
+    let mut live = particles.iter().count();
+    for (mut emitter, transform, velocity) in &mut query {
+        if !emitter.active {
+            emitter.accumulator = 0.0;
+            continue;
+        }
+
+        emitter.accumulator += emitter.config.rate * time.delta_seconds();
+        let amount = emitter.accumulator.floor();
+        emitter.accumulator -= amount;
+
+        let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        let position = translation.xy() + (rotation * emitter.offset.extend(0.0)).xy();
+        let direction = (rotation * emitter.direction.extend(0.0)).xy();
+        let base_velocity = velocity.map_or(Vec2::ZERO, |velocity| velocity.linvel);
+
+        for _ in 0..amount as u32 {
+            if live >= MAX_PARTICLES {
+                break;
+            }
+            spawn_particle(
+                &mut commands,
+                &mut rng.0,
+                &emitter.config,
+                position,
+                direction,
+                base_velocity,
+            );
+            live += 1;
+        }
+    }
+}
+
+fn effect_system(
+    mut commands: Commands,
+    mut rng: ResMut<ParticleRng>,
+    particles: Query<(), With<Particle>>,
+    mut effects: EventReader<ParticleEffect>,
+) {
+    let mut live = particles.iter().count();
+    for effect in effects.read() {
+        let config = effect.preset.config();
+        for _ in 0..config.burst {
+            if live >= MAX_PARTICLES {
+                return;
+            }
+            spawn_particle(
+                &mut commands,
+                &mut rng.0,
+                &config,
+                effect.position,
+                effect.direction,
+                effect.velocity,
+            );
+            live += 1;
+        }
+    }
+}
+
+fn spawn_particle(
+    commands: &mut Commands,
+    rng: &mut impl Rng,
+    config: &EmitterConfig,
+    position: Vec2,
+    direction: Vec2,
+    base_velocity: Vec2,
+) {
+    // no direction means a full circle
+    let (base_angle, spread) = if direction == Vec2::ZERO {
+        (0.0, std::f32::consts::PI)
+    } else {
+        (direction.y.atan2(direction.x), config.spread)
+    };
+    let angle = base_angle + rng.gen_range(-spread..=spread);
+    let speed = rng.gen_range(config.speed.clone());
+
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: config.color.0,
+                custom_size: Some(Vec2::splat(config.size.0)),
+                ..default()
+            },
+            // particles are drawn above the terrain
+            transform: Transform::from_translation(position.extend(1.0)),
+            ..default()
+        },
+        Particle {
+            velocity: base_velocity + Vec2::from_angle(angle) * speed,
+            age: 0.0,
+            lifetime: rng.gen_range(config.lifetime.clone()),
+            size: config.size,
+            color: config.color,
+            gravity: config.gravity,
+            collides: config.collides,
+        },
+    ));
+}
+
+/// move, age, fade and collide all particles
+fn particle_update_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    level: Option<Res<LevelMap>>,
+    mut query: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut particle, mut transform, mut sprite) in &mut query {
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        particle.velocity.y -= particle.gravity * delta;
+        let position = transform.translation.xy();
+        let mut next = position + particle.velocity * delta;
+
+        if let Some(level) = level.as_deref().filter(|_| particle.collides) {
+            if level.is_solid(next) {
+                // bounce off the axis that entered the terrain and lose most of the energy
+                if level.is_solid(Vec2::new(next.x, position.y)) {
+                    particle.velocity.x *= -0.4;
+                }
+                if level.is_solid(Vec2::new(position.x, next.y)) {
+                    particle.velocity.y *= -0.4;
+                }
+                particle.velocity *= 0.7;
+                next = position;
+            }
+        }
+        transform.translation.x = next.x;
+        transform.translation.y = next.y;
+
+        let t = particle.age / particle.lifetime;
+        sprite.color = lerp_color(particle.color.0, particle.color.1, t);
+        sprite.custom_size = Some(Vec2::splat(
+            particle.size.0 + (particle.size.1 - particle.size.0) * t,
+        ));
+    }
+}
+
+fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    let from = from.as_rgba_f32();
+    let to = to.as_rgba_f32();
+    Color::rgba(
+        from[0] + (to[0] - from[0]) * t,
+        from[1] + (to[1] - from[1]) * t,
+        from[2] + (to[2] - from[2]) * t,
+        from[3] + (to[3] - from[3]) * t,
+    )
+}
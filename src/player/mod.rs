@@ -1,34 +1,128 @@
 use bevy::prelude::*;
-use bevy_rapier2d::dynamics::ExternalImpulse;
+
+use crate::health::Team;
+use crate::level::LevelDefinition;
+use crate::ship::{apply_ship_thrust_system, spawn_ship, ShipDefinitions, ShipInput};
+use crate::state::{GameMode, GameState, GameplaySet};
+
+/// ships a player has in a campaign run, including the first one
+const CAMPAIGN_LIVES: u32 = 3;
 
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            FixedUpdate,
-            (player_movement_system, camera_follow_player_system),
-        );
+        app.init_resource::<Lives>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::Loading,
+                    to: GameState::InGame,
+                },
+                reset_lives,
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    player_input_system.before(apply_ship_thrust_system),
+                    respawn_player_system,
+                    (assign_player_camera_system, camera_follow_player_system).chain(),
+                )
+                    .in_set(GameplaySet),
+            );
     }
 }
 
-/// player component
+/// player component, marks the ship controlled by the keyboard
 #[derive(Component)]
-pub(crate) struct Player {
-    /// linear speed in meters per second
-    pub(crate) movement_impulse: f32,
-    /// rotation speed in radians per second
-    pub(crate) rotation_impulse: f32,
+pub(crate) struct Player;
+
+/// ships left to the player, including the one flying. A destroyed ship is replaced at the start
+/// of the level as long as there is more than one.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Lives(pub(crate) u32);
+
+/// camera showing the view of a player, with a split screen every player has their own
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct PlayerCamera {
+    pub(crate) player: Entity,
+}
+
+/// spawn the player ship at the start of the level
+pub(crate) fn spawn_player(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    ships: &ShipDefinitions,
+    level: &LevelDefinition,
+) {
+    let player_ship = ships
+        .get(&level.player_ship)
+        .unwrap_or_else(|| panic!("ship definition {:?} not found", level.player_ship));
+    let (x, y) = level.player_start;
+    spawn_ship(
+        commands,
+        asset_server,
+        player_ship,
+        Transform::from_xyz(x, y, 0.0),
+    )
+    .insert((Player, Team::Player));
 }
 
-/// applying torque impulse and movement impulse based on keyboard input.
-fn player_movement_system(
-    time: Res<Time>,
+/// only the campaign has spare ships, time trials and races end with the first one
+fn reset_lives(mut lives: ResMut<Lives>, mode: Res<GameMode>) {
+    lives.0 = match *mode {
+        GameMode::Campaign => CAMPAIGN_LIVES,
+        GameMode::TimeTrial | GameMode::Race => 1,
+    };
+}
+
+/// replace a destroyed player ship while there are lives left, the game is over after the last
+fn respawn_player_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ships: Res<ShipDefinitions>,
+    level: Res<LevelDefinition>,
+    players: Query<(), With<Player>>,
+    mut lives: ResMut<Lives>,
+) {
+    if !players.is_empty() || lives.0 <= 1 {
+        return;
+    }
+    lives.0 -= 1;
+    spawn_player(&mut commands, &asset_server, &ships, &level);
+}
+
+/// give new player ships a camera, the one of a destroyed ship or one that shows no one yet
+fn assign_player_camera_system(
+    mut commands: Commands,
+    new_players: Query<Entity, Added<Player>>,
+    players: Query<(), With<Player>>,
+    cameras: Query<(Entity, Option<&PlayerCamera>), With<Camera>>,
+) {
+    if new_players.is_empty() {
+        return;
+    }
+    let mut free_cameras = cameras.iter().filter_map(|(camera, view)| match view {
+        Some(view) if players.contains(view.player) => None,
+        _ => Some(camera),
+    });
+    for player in &new_players {
+        let Some(camera) = free_cameras.next() else {
+            warn!("no camera left for player {player:?}");
+            return;
+        };
+        commands.entity(camera).insert(PlayerCamera { player });
+    }
+}
+
+/// translate the keyboard state into the control input of the player ship. The ship systems
+/// turn the input into forces, so nothing here depends on the tick rate.
+pub(crate) fn player_input_system(
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(&Player, &mut ExternalImpulse, &Transform)>,
+    mut query: Query<&mut ShipInput, With<Player>>,
 ) {
-    let (player, mut external_impulse, transform) = query
-        .get_single_mut()
-        .expect("No or more than one Player entity found");
+    // the ship can be destroyed by an earlier tick of the same frame, before the game is over
+    let Ok(mut input) = query.get_single_mut() else {
+        return;
+    };
     let mut rotation_factor = 0.0;
     let mut movement_factor = 0.0;
 
@@ -48,27 +142,21 @@ fn player_movement_system(
         movement_factor -= 1.0;
     }
 
-    // rotation
-    external_impulse.torque_impulse =
-        rotation_factor * player.rotation_impulse * time.delta_seconds();
-
-    // calculate the linear impulse based on the rotation of the ship
-
-    let magnitude = movement_factor * player.movement_impulse * time.delta_seconds();
-    let direction = transform.rotation.mul_vec3(Vec3::Y);
-
-    external_impulse.impulse = Vec2::new(direction.x * magnitude, direction.y * magnitude);
+    input.rotation_factor = rotation_factor;
+    input.movement_factor = movement_factor;
+    input.fire = keyboard_input.pressed(KeyCode::Space);
+    input.tractor = keyboard_input.pressed(KeyCode::ShiftLeft);
 }
 
-// move camera to follow player
+// move cameras to follow their player
 fn camera_follow_player_system(
     player_query: Query<&Transform, (With<Player>, Without<Camera>)>,
-    mut camera_query: Query<&mut Transform, (With<Camera>, Without<Player>)>,
+    mut camera_query: Query<(&mut Transform, &PlayerCamera), Without<Player>>,
 ) {
-    if let Ok(player_transform) = player_query.get_single() {
-        for mut camera_transform in camera_query.iter_mut() {
+    for (mut camera_transform, view) in camera_query.iter_mut() {
+        if let Ok(player_transform) = player_query.get(view.player) {
             camera_transform.translation = player_transform.translation;
             // camera_transform.look_at(player_transform.translation, Vec3::X);
         }
-    };
+    }
 }
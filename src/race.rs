@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::level::LevelDefinition;
+use crate::menu::despawn_screen;
+use crate::player::Player;
+use crate::state::{GameMode, GameState, GameplaySet};
+use crate::ticks_to_seconds;
+
+pub struct RacePlugin;
+impl Plugin for RacePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                from: GameState::Loading,
+                to: GameState::InGame,
+            },
+            start_race.run_if(resource_equals(GameMode::Race)),
+        )
+        .add_systems(OnEnter(GameState::Loading), despawn_screen::<RaceHud>)
+        .add_systems(OnEnter(GameState::MainMenu), despawn_screen::<RaceHud>)
+        .add_systems(
+            FixedUpdate,
+            checkpoint_system
+                .after(PhysicsSet::Writeback)
+                .run_if(resource_exists::<RaceProgress>())
+                .in_set(GameplaySet),
+        )
+        .add_systems(
+            Update,
+            race_hud_system
+                .run_if(resource_exists::<RaceProgress>())
+                .in_set(GameplaySet),
+        );
+    }
+}
+
+/// race track of a level
+#[derive(Deserialize, Clone, Debug)]
+pub struct RaceDefinition {
+    pub laps: u32,
+    /// gates in the order they have to be flown through, the first one is the start and finish
+    /// line
+    pub checkpoints: Vec<CheckpointDefinition>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct CheckpointDefinition {
+    pub position: (f32, f32),
+    /// width and height of the gate in pixels
+    pub size: (f32, f32),
+    /// counter clockwise in degrees
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+/// a gate of the race track, a rapier sensor
+#[derive(Component)]
+struct Checkpoint {
+    index: usize,
+}
+
+/// state of the race, fed by the intersection events of the checkpoint sensors. Times are in
+/// FixedUpdate ticks.
+#[derive(Resource)]
+pub struct RaceProgress {
+    pub laps: u32,
+    checkpoint_count: usize,
+    /// ticks since the start
+    pub ticks: u32,
+    pub players: HashMap<Entity, PlayerRace>,
+    /// fastest lap of any player
+    pub best_lap: Option<u32>,
+    /// fastest time from the start of a lap to each checkpoint, of any player and lap
+    pub best_splits: Vec<Option<u32>>,
+}
+
+/// race of one player
+#[derive(Clone, Debug, Default)]
+pub struct PlayerRace {
+    /// index of the checkpoint that counts next
+    pub next_checkpoint: usize,
+    /// laps completed
+    pub lap: u32,
+    /// tick the current lap started at
+    lap_start: u32,
+    /// ticks from the start of the current lap to each checkpoint passed in it
+    pub splits: Vec<u32>,
+    pub lap_times: Vec<u32>,
+    /// tick the last lap was completed at
+    pub finished: Option<u32>,
+}
+
+/// text overlay with laps and times
+#[derive(Component)]
+struct RaceHud;
+
+/// place the checkpoint gates and start the clock
+fn start_race(mut commands: Commands, level: Res<LevelDefinition>) {
+    let Some(race) = &level.race else {
+        warn!("level has no race track");
+        return;
+    };
+    if race.checkpoints.is_empty() {
+        warn!("race track has no checkpoints");
+        return;
+    }
+
+    for (index, checkpoint) in race.checkpoints.iter().enumerate() {
+        let (x, y) = checkpoint.position;
+        let size = Vec2::from(checkpoint.size);
+        let color = if index == 0 {
+            Color::rgba(1.0, 1.0, 1.0, 0.3)
+        } else {
+            Color::rgba(0.2, 0.6, 1.0, 0.3)
+        };
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, -0.1)
+                    .with_rotation(Quat::from_rotation_z(checkpoint.rotation.to_radians())),
+                ..default()
+            },
+            Checkpoint { index },
+            Collider::cuboid(size.x / 2.0, size.y / 2.0),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+        ));
+    }
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            ..default()
+        }),
+        RaceHud,
+    ));
+
+    commands.insert_resource(RaceProgress {
+        laps: race.laps.max(1),
+        checkpoint_count: race.checkpoints.len(),
+        ticks: 0,
+        players: HashMap::new(),
+        best_lap: None,
+        best_splits: vec![None; race.checkpoints.len()],
+    });
+}
+
+/// advance the clock and count the checkpoints the players fly through. Gates passed out of order
+/// are ignored. The race ends once every player completed the last lap.
+fn checkpoint_system(
+    mut progress: ResMut<RaceProgress>,
+    mut collision_events: EventReader<CollisionEvent>,
+    checkpoints: Query<&Checkpoint>,
+    players: Query<Entity, With<Player>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    progress.ticks += 1;
+    let ticks = progress.ticks;
+    let checkpoint_count = progress.checkpoint_count;
+    for player in &players {
+        progress
+            .players
+            .entry(player)
+            .or_insert_with(|| PlayerRace {
+                // the players start on the start line, the first lap ends there
+                next_checkpoint: 1 % checkpoint_count,
+                ..default()
+            });
+    }
+
+    for event in collision_events.read() {
+        let CollisionEvent::Started(a, b, _) = *event else {
+            continue;
+        };
+        let (checkpoint, player) = match (checkpoints.get(a), checkpoints.get(b)) {
+            (Ok(checkpoint), _) if players.contains(b) => (checkpoint, b),
+            (_, Ok(checkpoint)) if players.contains(a) => (checkpoint, a),
+            _ => continue,
+        };
+        pass_checkpoint(&mut progress, player, checkpoint.index, ticks);
+    }
+
+    let all_finished = !progress.players.is_empty()
+        && progress
+            .players
+            .values()
+            .all(|race| race.finished.is_some());
+    if all_finished {
+        next_state.set(GameState::LevelComplete);
+    }
+}
+
+fn pass_checkpoint(progress: &mut RaceProgress, player: Entity, index: usize, ticks: u32) {
+    let checkpoint_count = progress.checkpoint_count;
+    let laps = progress.laps;
+    let Some(race) = progress.players.get_mut(&player) else {
+        return;
+    };
+    if race.finished.is_some() || index != race.next_checkpoint {
+        return;
+    }
+    let split = ticks - race.lap_start;
+    race.splits.push(split);
+    race.next_checkpoint = (index + 1) % checkpoint_count;
+    let best_split = &mut progress.best_splits[index];
+    if best_split.is_none_or(|best| split < best) {
+        *best_split = Some(split);
+    }
+    if index != 0 {
+        return;
+    }
+
+    race.lap += 1;
+    race.lap_times.push(split);
+    race.lap_start = ticks;
+    race.splits.clear();
+    info!(
+        "{player:?} completed lap {} in {:.2} s",
+        race.lap,
+        ticks_to_seconds(split)
+    );
+    if race.lap >= laps {
+        race.finished = Some(ticks);
+        info!(
+            "{player:?} finished the race in {:.2} s",
+            ticks_to_seconds(ticks)
+        );
+    }
+    if progress.best_lap.is_none_or(|best| split < best) {
+        progress.best_lap = Some(split);
+    }
+}
+
+fn format_time(ticks: Option<u32>) -> String {
+    ticks.map_or_else(
+        || "--".to_string(),
+        |ticks| format!("{:.2}", ticks_to_seconds(ticks)),
+    )
+}
+
+/// show laps, the running lap time and the best times
+fn race_hud_system(progress: Res<RaceProgress>, mut query: Query<&mut Text, With<RaceHud>>) {
+    let mut players: Vec<_> = progress.players.iter().collect();
+    players.sort_by_key(|(entity, _)| **entity);
+
+    let mut lines = Vec::new();
+    for (number, (_, race)) in players.iter().enumerate() {
+        let lap_time = race
+            .finished
+            .is_none()
+            .then(|| progress.ticks - race.lap_start);
+        lines.push(format!(
+            "P{}  lap {}/{}  {}  last {}",
+            number + 1,
+            (race.lap + 1).min(progress.laps),
+            progress.laps,
+            format_time(lap_time),
+            format_time(race.lap_times.last().copied()),
+        ));
+    }
+    lines.push(format!("best lap {}", format_time(progress.best_lap)));
+    let splits: Vec<String> = progress
+        .best_splits
+        .iter()
+        .skip(1)
+        .map(|split| format_time(*split))
+        .collect();
+    if !splits.is_empty() {
+        lines.push(format!("best splits {}", splits.join(" / ")));
+    }
+
+    let label = lines.join("\n");
+    for mut text in &mut query {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
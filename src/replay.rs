@@ -0,0 +1,307 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::ecs::schedule::ExecutorKind;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::difficulty::Difficulty;
+use crate::loading::{start_level_loading, CurrentLevel};
+use crate::player::{player_input_system, Player};
+use crate::ship::{apply_ship_thrust_system, ShipInput};
+use crate::state::{GameMode, GameState, GameplaySet};
+use crate::storage::{user_data_dir, write_ron};
+
+/// directory finished runs are saved to
+fn replay_directory() -> PathBuf {
+    user_data_dir().join("replays")
+}
+
+/// button bits of one tick, see [`TickInput`]
+const FIRE: u8 = 1 << 0;
+const TRACTOR: u8 = 1 << 1;
+
+/// steps per unit of the recorded rotation and movement factors
+const FACTOR_STEPS: f32 = 100.0;
+
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        // the multi threaded executor runs independent systems in a different order every tick,
+        // a replay needs the same order every time
+        app.edit_schedule(FixedUpdate, |schedule| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        })
+        .insert_resource(GameRng::seeded(0))
+        .init_resource::<ReplayRecorder>()
+        .add_systems(
+            OnEnter(GameState::Loading),
+            start_run.before(start_level_loading),
+        )
+        .add_systems(OnEnter(GameState::GameOver), save_replay)
+        .add_systems(OnEnter(GameState::LevelComplete), save_replay)
+        .add_systems(
+            OnTransition {
+                from: GameState::Paused,
+                to: GameState::MainMenu,
+            },
+            save_replay,
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                replay_playback_system.run_if(resource_exists::<ReplayPlayback>()),
+                replay_record_system,
+            )
+                .chain()
+                .after(player_input_system)
+                .before(apply_ship_thrust_system)
+                .in_set(GameplaySet),
+        );
+    }
+}
+
+/// the random number generator of everything that affects gameplay, seeded at the start of every
+/// run so replays get the same numbers. Purely visual randomness like particles does not use it.
+#[derive(Resource)]
+pub struct GameRng(StdRng);
+
+impl GameRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+/// everything needed to play a run again: where and how it started and what the players did
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replay {
+    /// path of the level definition
+    pub level: String,
+    pub difficulty: Difficulty,
+    #[serde(default)]
+    pub mode: GameMode,
+    pub seed: u64,
+    /// one track per player ship, in the order the ships were spawned
+    pub players: Vec<InputTrack>,
+}
+
+impl Replay {
+    /// read and parse a replay file, panics like the ship definitions do
+    pub fn load(path: &str) -> Self {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("could not read replay {path}: {err}"));
+        ron::from_str(&source).unwrap_or_else(|err| panic!("could not parse replay {path}: {err}"))
+    }
+}
+
+/// inputs of one player, tick by tick
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct InputTrack {
+    /// run length encoded: the input and for how many ticks it was held
+    runs: Vec<(TickInput, u32)>,
+}
+
+impl InputTrack {
+    fn push(&mut self, input: TickInput) {
+        match self.runs.last_mut() {
+            Some((last, count)) if *last == input => *count += 1,
+            _ => self.runs.push((input, 1)),
+        }
+    }
+
+    /// number of recorded ticks
+    pub fn len(&self) -> u32 {
+        self.runs.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// the input of a ship for one tick. The factors of [`ShipInput`] are kept in steps of
+/// 1 / [`FACTOR_STEPS`], so partial throttle like that of headless scripts survives, the buttons
+/// are packed into a byte.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct TickInput {
+    rotation: i8,
+    movement: i8,
+    buttons: u8,
+}
+
+impl TickInput {
+    fn from_input(input: &ShipInput) -> Self {
+        let quantize = |factor: f32| (factor.clamp(-1.0, 1.0) * FACTOR_STEPS).round() as i8;
+        let mut buttons = 0;
+        if input.fire {
+            buttons |= FIRE;
+        }
+        if input.tractor {
+            buttons |= TRACTOR;
+        }
+        Self {
+            rotation: quantize(input.rotation_factor),
+            movement: quantize(input.movement_factor),
+            buttons,
+        }
+    }
+
+    fn apply(self, input: &mut ShipInput) {
+        input.rotation_factor = self.rotation as f32 / FACTOR_STEPS;
+        input.movement_factor = self.movement as f32 / FACTOR_STEPS;
+        input.fire = self.buttons & FIRE != 0;
+        input.tractor = self.buttons & TRACTOR != 0;
+    }
+}
+
+/// the run currently being recorded, every run is recorded
+#[derive(Resource, Default)]
+pub(crate) struct ReplayRecorder(Option<Replay>);
+
+impl ReplayRecorder {
+    /// the recording so far, `None` outside of a run
+    pub(crate) fn current(&self) -> Option<&Replay> {
+        self.0.as_ref()
+    }
+}
+
+/// a replay being played back, the players follow it instead of the keyboard
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    /// per track: index of the current run and ticks of it already played
+    cursors: Vec<(usize, u32)>,
+    finished: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            cursors: vec![(0, 0); replay.players.len()],
+            replay,
+            finished: false,
+        }
+    }
+
+    /// input of the track for the next tick, `None` once the track is played through
+    fn next(&mut self, track: usize) -> Option<TickInput> {
+        let runs = &self.replay.players.get(track)?.runs;
+        let (run, played) = self.cursors.get_mut(track)?;
+        let &(input, count) = runs.get(*run)?;
+        *played += 1;
+        if *played >= count {
+            *run += 1;
+            *played = 0;
+        }
+        Some(input)
+    }
+}
+
+/// seed the game for a new run and start recording it. A replay being played back brings its
+/// own seed, difficulty and level.
+fn start_run(
+    mut rng: ResMut<GameRng>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut level: ResMut<CurrentLevel>,
+    mut difficulty: ResMut<Difficulty>,
+    mut mode: ResMut<GameMode>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    let seed = match playback {
+        Some(mut playback) => {
+            level.0 = playback.replay.level.clone();
+            *difficulty = playback.replay.difficulty;
+            *mode = playback.replay.mode;
+            // the run could be a restart after the replay ended
+            *playback = ReplayPlayback::new(playback.replay.clone());
+            playback.replay.seed
+        }
+        None => rand::thread_rng().next_u64(),
+    };
+    *rng = GameRng::seeded(seed);
+    recorder.0 = Some(Replay {
+        level: level.0.clone(),
+        difficulty: *difficulty,
+        mode: *mode,
+        seed,
+        players: Vec::new(),
+    });
+}
+
+/// the ships of the players in the order they were spawned, which is the order of the tracks
+fn ordered_players<T>(query: impl Iterator<Item = (Entity, T)>) -> Vec<T> {
+    let mut players: Vec<_> = query.collect();
+    players.sort_by_key(|(entity, _)| *entity);
+    players.into_iter().map(|(_, input)| input).collect()
+}
+
+/// overwrite the keyboard input of the players with the replay
+fn replay_playback_system(
+    mut playback: ResMut<ReplayPlayback>,
+    mut query: Query<(Entity, &mut ShipInput), With<Player>>,
+) {
+    let mut any_left = false;
+    for (track, mut input) in ordered_players(query.iter_mut()).into_iter().enumerate() {
+        let recorded = playback.next(track);
+        any_left |= recorded.is_some();
+        recorded.unwrap_or_default().apply(&mut input);
+    }
+    if !any_left && !playback.finished {
+        info!("replay finished");
+        playback.finished = true;
+    }
+}
+
+/// append the input the player ships got this tick to the recording. The ships fly with the
+/// recorded input, so the run plays back exactly even if the factors had to be rounded.
+pub(crate) fn replay_record_system(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut query: Query<(Entity, &mut ShipInput), With<Player>>,
+) {
+    let Some(replay) = &mut recorder.0 else {
+        return;
+    };
+    for (track, mut input) in ordered_players(query.iter_mut()).into_iter().enumerate() {
+        if replay.players.len() <= track {
+            replay.players.push(InputTrack::default());
+        }
+        let recorded = TickInput::from_input(&input);
+        recorded.apply(&mut input);
+        replay.players[track].push(recorded);
+    }
+}
+
+/// write the recorded run to the replay directory, a failure is not worth ending the game over
+fn save_replay(mut recorder: ResMut<ReplayRecorder>) {
+    let Some(replay) = recorder.0.take() else {
+        return;
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = replay_directory().join(format!("{timestamp}.ron"));
+    match write_ron(&path, &replay) {
+        Ok(()) => info!(
+            "saved replay of {} ticks to {}",
+            replay.players.first().map_or(0, InputTrack::len),
+            path.display()
+        ),
+        Err(err) => warn!("could not save replay {}: {err}", path.display()),
+    }
+}
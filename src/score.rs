@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cargo::{cargo_delivery_system, CargoDelivered};
+use crate::difficulty::Difficulty;
+use crate::health::{death_system, DestroyedEvent, Team};
+use crate::loading::CurrentLevel;
+use crate::objectives::{objective_system, ObjectiveCompleted};
+use crate::replay::ReplayPlayback;
+use crate::state::{GameMode, GameState, GameplaySet};
+use crate::storage::{read_ron, user_data_dir, write_ron};
+
+/// points for destroying an enemy
+const KILL_POINTS: u32 = 100;
+
+/// points for delivering a cargo pod
+const CARGO_POINTS: u32 = 500;
+
+/// points for completing an objective
+const OBJECTIVE_POINTS: u32 = 1000;
+
+/// increase of the combo multiplier per kill
+const COMBO_STEP: f32 = 0.25;
+
+/// the combo multiplier does not grow beyond this
+const COMBO_MAX: f32 = 4.0;
+
+/// seconds the combo multiplier holds after a kill before it starts to decay
+const COMBO_HOLD: f32 = 2.0;
+
+/// decay of the combo multiplier per second once the hold ran out
+const COMBO_DECAY: f32 = 0.5;
+
+/// entries kept per level
+const HIGH_SCORE_ENTRIES: usize = 10;
+
+pub struct ScorePlugin;
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Score>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::Loading,
+                    to: GameState::InGame,
+                },
+                reset_score,
+            )
+            .add_systems(
+                FixedUpdate,
+                score_system
+                    .after(death_system)
+                    .after(cargo_delivery_system)
+                    .after(objective_system)
+                    .in_set(GameplaySet),
+            );
+        // replays would enter their run a second time
+        for state in [GameState::GameOver, GameState::LevelComplete] {
+            app.add_systems(
+                OnEnter(state),
+                save_high_score
+                    .run_if(resource_equals(GameMode::Campaign))
+                    .run_if(not(resource_exists::<ReplayPlayback>())),
+            );
+        }
+    }
+}
+
+/// points of the current run
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct Score {
+    pub points: u64,
+    /// factor applied to every gain, grows with quick kills
+    pub combo: f32,
+    /// seconds until the combo starts to decay
+    combo_hold: f32,
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Self {
+            points: 0,
+            combo: 1.0,
+            combo_hold: 0.0,
+        }
+    }
+}
+
+impl Score {
+    /// add points, scaled by the combo multiplier
+    pub fn add(&mut self, points: u32) {
+        self.points += (points as f32 * self.combo).round() as u64;
+    }
+
+    /// a kill scores and keeps the combo going
+    fn kill(&mut self) {
+        self.add(KILL_POINTS);
+        self.combo = (self.combo + COMBO_STEP).min(COMBO_MAX);
+        self.combo_hold = COMBO_HOLD;
+    }
+
+    fn decay(&mut self, delta: f32) {
+        let decay_time = (delta - self.combo_hold).max(0.0);
+        self.combo_hold = (self.combo_hold - delta).max(0.0);
+        self.combo = (self.combo - COMBO_DECAY * decay_time).max(1.0);
+    }
+}
+
+/// one line of a high score table
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HighScoreEntry {
+    pub points: u64,
+    pub difficulty: Difficulty,
+    /// whether the level was completed, or the run ended with the player ship destroyed
+    pub completed: bool,
+    /// unix time in seconds of the end of the run
+    pub timestamp: u64,
+}
+
+/// best runs of a level, highest score first
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct HighScoreTable {
+    pub entries: Vec<HighScoreEntry>,
+}
+
+impl HighScoreTable {
+    /// insert an entry at its rank and drop what falls off the end, returns the rank if the
+    /// entry made it into the table
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| other.points < entry.points)
+            .unwrap_or(self.entries.len());
+        if rank >= HIGH_SCORE_ENTRIES {
+            return None;
+        }
+        self.entries.insert(rank, entry);
+        self.entries.truncate(HIGH_SCORE_ENTRIES);
+        Some(rank)
+    }
+}
+
+/// the high score file as stored on disk. Every change of the format gets a new variant, older
+/// variants are converted when they are read, so existing tables survive updates.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum HighScoreFile {
+    V1(HighScoreTable),
+}
+
+impl HighScoreFile {
+    fn into_table(self) -> HighScoreTable {
+        match self {
+            HighScoreFile::V1(table) => table,
+        }
+    }
+}
+
+/// file with the high scores of a level, one per level definition
+fn high_score_path(level: &CurrentLevel) -> PathBuf {
+    user_data_dir()
+        .join("highscores")
+        .join(format!("{}.ron", level.name()))
+}
+
+/// read the high scores of a level, an unreadable table starts over empty
+pub fn load_high_scores(level: &CurrentLevel) -> HighScoreTable {
+    let path = high_score_path(level);
+    match read_ron::<HighScoreFile>(&path) {
+        Some(Ok(file)) => file.into_table(),
+        Some(Err(err)) => {
+            warn!("could not read high scores {}: {err}", path.display());
+            HighScoreTable::default()
+        }
+        None => HighScoreTable::default(),
+    }
+}
+
+fn reset_score(mut score: ResMut<Score>) {
+    *score = Score::default();
+}
+
+/// score kills, deliveries and objectives and let the combo decay
+fn score_system(
+    time: Res<Time>,
+    mut score: ResMut<Score>,
+    mut destroyed: EventReader<DestroyedEvent>,
+    mut delivered: EventReader<CargoDelivered>,
+    mut objectives: EventReader<ObjectiveCompleted>,
+) {
+    // a settled combo is left alone, the HUD reacts to changes of the score
+    if score.combo > 1.0 || score.combo_hold > 0.0 {
+        score.decay(time.delta_seconds());
+    }
+    for event in destroyed.read() {
+        if event.team == Some(Team::Enemy) && event.part_of.is_none() {
+            score.kill();
+        }
+    }
+    for _ in delivered.read() {
+        score.add(CARGO_POINTS);
+    }
+    for _ in objectives.read() {
+        score.add(OBJECTIVE_POINTS);
+    }
+}
+
+/// enter the finished run into the high score table of the level
+fn save_high_score(
+    score: Res<Score>,
+    difficulty: Res<Difficulty>,
+    level: Res<CurrentLevel>,
+    state: Res<State<GameState>>,
+) {
+    if score.points == 0 {
+        return;
+    }
+    let mut table = load_high_scores(&level);
+    let entry = HighScoreEntry {
+        points: score.points,
+        difficulty: *difficulty,
+        completed: *state.get() == GameState::LevelComplete,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs()),
+    };
+    let Some(rank) = table.insert(entry) else {
+        info!("score {} did not make the high scores", score.points);
+        return;
+    };
+    info!("score {} is high score #{}", score.points, rank + 1);
+    let path = high_score_path(&level);
+    if let Err(err) = write_ron(&path, &HighScoreFile::V1(table)) {
+        warn!("could not save high scores {}: {err}", path.display());
+    }
+}
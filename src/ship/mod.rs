@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::health::Health;
+use crate::particles::{ParticleEmitter, ParticlePreset};
+use crate::state::GameplaySet;
+use crate::weapon::WeaponDefinition;
+use crate::PIXELS_PER_METER;
+
+/// file containing all ship archetypes, keyed by name
+const SHIP_DEFINITIONS_PATH: &str = "assets/ships.ron";
+
+pub struct ShipPlugin;
+impl Plugin for ShipPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ShipDefinitions::load(SHIP_DEFINITIONS_PATH))
+            .add_systems(
+                FixedUpdate,
+                apply_ship_thrust_system
+                    .before(PhysicsSet::SyncBackend)
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// all ship archetypes known to the game, loaded from [`SHIP_DEFINITIONS_PATH`]
+#[derive(Resource, Deserialize, Default)]
+#[serde(transparent)]
+pub struct ShipDefinitions(HashMap<String, ShipDefinition>);
+
+impl ShipDefinitions {
+    /// read and parse a ron file with ship definitions. Panics if the file is missing or
+    /// malformed, like the level loading does, as there is nothing sensible to fall back to.
+    pub fn load(path: &str) -> Self {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("could not read ship definitions {path}: {err}"));
+        ron::from_str(&source)
+            .unwrap_or_else(|err| panic!("could not parse ship definitions {path}: {err}"))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ShipDefinition> {
+        self.0.get(name)
+    }
+}
+
+/// archetype of a ship, everything needed to spawn it and to tune its handling
+#[derive(Deserialize, Clone, Debug)]
+pub struct ShipDefinition {
+    /// mass of the ship in kilograms, overrides the mass derived from the hull area
+    pub mass: f32,
+    /// force of the main engine along the ship forward axis (+Y) in newtons
+    pub thrust: f32,
+    /// torque of the rotation thrusters in newton meters
+    pub torque: f32,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    /// hit points of the hull
+    pub health: f32,
+    /// outline of the ship in pixels relative to its center, the ship faces +Y.
+    /// The collider is the convex hull of these points.
+    pub hull: Vec<(f32, f32)>,
+    pub sprite: ShipSprite,
+    /// names of the weapons mounted on the ship, in firing slot order
+    #[serde(default)]
+    pub weapons: Vec<String>,
+}
+
+/// visual representation of a ship
+#[derive(Deserialize, Clone, Debug)]
+pub struct ShipSprite {
+    /// size of the sprite in pixels
+    pub size: (f32, f32),
+    /// tint of the sprite, plain color if there is no texture
+    pub color: (f32, f32, f32),
+    /// optional texture path relative to the assets folder
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+/// engine configuration of a ship, read by the movement systems
+#[derive(Component)]
+pub(crate) struct Thrusters {
+    /// force of the main engine in newtons
+    pub(crate) thrust: f32,
+    /// torque of the rotation thrusters in newton meters
+    pub(crate) torque: f32,
+}
+
+/// what the pilot of a ship (player or AI) currently asks the engines to do
+#[derive(Component, Default, Clone, Copy, PartialEq, Debug)]
+pub(crate) struct ShipInput {
+    /// main engine throttle, 1.0 is full forward thrust and -1.0 full reverse thrust
+    pub(crate) movement_factor: f32,
+    /// rotation thrusters, 1.0 turns counter clockwise at full torque and -1.0 clockwise
+    pub(crate) rotation_factor: f32,
+    /// whether the primary weapon should fire
+    pub(crate) fire: bool,
+    /// tractor beam button, grabs or lets go of cargo when pressed
+    pub(crate) tractor: bool,
+}
+
+/// weapons mounted on a ship
+#[derive(Component)]
+pub(crate) struct Loadout {
+    /// weapon names, see [`crate::weapon::WeaponDefinitions`]
+    pub(crate) weapons: Vec<String>,
+    /// seconds until each weapon can fire again
+    pub(crate) cooldowns: Vec<f32>,
+    /// rounds each weapon fired since the ship was spawned
+    pub(crate) rounds_fired: Vec<u32>,
+}
+
+impl Loadout {
+    /// rounds left for the weapon at `index`, `None` when its ammo is unlimited
+    pub(crate) fn ammo_left(&self, index: usize, weapon: &WeaponDefinition) -> Option<u32> {
+        let ammo = weapon.ammo?;
+        Some(ammo.saturating_sub(self.rounds_fired[index]))
+    }
+}
+
+/// spawn a ship with sprite, rigid body, collider and engine from its definition.
+/// Returns the entity commands so callers can add the controlling components (player, AI).
+pub fn spawn_ship<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    asset_server: &AssetServer,
+    definition: &ShipDefinition,
+    transform: Transform,
+) -> bevy::ecs::system::EntityCommands<'w, 's, 'a> {
+    let hull: Vec<Vec2> = definition
+        .hull
+        .iter()
+        .map(|&(x, y)| Vec2::new(x, y))
+        .collect();
+    let collider = Collider::convex_hull(&hull).unwrap_or_else(|| {
+        // degenerate hull, fall back to a box covering the sprite
+        warn!("ship hull is degenerate, using the sprite size as collider");
+        Collider::cuboid(
+            definition.sprite.size.0 / 2.0,
+            definition.sprite.size.1 / 2.0,
+        )
+    });
+
+    let (r, g, b) = definition.sprite.color;
+    let texture = definition
+        .sprite
+        .texture
+        .as_ref()
+        .map(|path| asset_server.load(path.clone()))
+        .unwrap_or_default();
+
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(r, g, b),
+                custom_size: Some(Vec2::new(
+                    definition.sprite.size.0,
+                    definition.sprite.size.1,
+                )),
+                ..default()
+            },
+            texture,
+            transform,
+            ..default()
+        },
+        Thrusters {
+            thrust: definition.thrust,
+            torque: definition.torque,
+        },
+        Loadout {
+            weapons: definition.weapons.clone(),
+            cooldowns: vec![0.0; definition.weapons.len()],
+            rounds_fired: vec![0; definition.weapons.len()],
+        },
+        Health::new(definition.health),
+        RigidBody::Dynamic,
+        collider,
+        ColliderMassProperties::Mass(definition.mass),
+        Damping {
+            linear_damping: definition.linear_damping,
+            angular_damping: definition.angular_damping,
+        },
+        ShipInput::default(),
+        // exhaust leaves the ship at its tail, opposite to the thrust direction
+        ParticleEmitter::new(
+            ParticlePreset::Exhaust,
+            Vec2::new(0.0, -definition.sprite.size.1 / 2.0),
+            Vec2::NEG_Y,
+        ),
+        ExternalForce::default(),
+        Velocity::default(),
+        Ccd::enabled(),
+    ))
+}
+
+/// convert a ship input into the engine force and torque in physical units (newtons and newton
+/// meters), with the force pointing along the ship forward axis given by `rotation`.
+pub(crate) fn engine_force(
+    thrusters: &Thrusters,
+    input: &ShipInput,
+    rotation: Quat,
+) -> (Vec2, f32) {
+    let direction = (rotation * Vec3::Y).xy();
+    let force = direction * thrusters.thrust * input.movement_factor.clamp(-1.0, 1.0);
+    let torque = thrusters.torque * input.rotation_factor.clamp(-1.0, 1.0);
+    (force, torque)
+}
+
+/// apply the engine force of every ship as a continuous rapier force.
+///
+/// The force is not scaled by the tick duration: rapier keeps applying it on every physics step
+/// and integrates it with the step length, so the trajectory of a ship only depends on how long
+/// the input is held and not on the rate of the fixed schedule. Rapier works in pixel units, so
+/// forces are scaled by [`PIXELS_PER_METER`]. Torques are not: bodies turn in radians and their
+/// inertia is computed from the shapes in meters, so rapier takes them in newton meters as they are.
+pub(crate) fn apply_ship_thrust_system(
+    mut query: Query<(&Thrusters, &ShipInput, &Transform, &mut ExternalForce)>,
+) {
+    for (thrusters, input, transform, mut external_force) in &mut query {
+        let (force, torque) = engine_force(thrusters, input, transform.rotation);
+        let force = force * PIXELS_PER_METER;
+
+        // only touch the component when the force changes so rapier does not have to resync
+        if external_force.force != force || external_force.torque != torque {
+            external_force.force = force;
+            external_force.torque = torque;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::app::ScheduleRunnerPlugin;
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::sync_physics_timestep;
+
+    /// seconds of the flight, the controls are held for the first half
+    const FLIGHT_SECONDS: f64 = 2.0;
+
+    /// fixed update ticks flown so far
+    #[derive(Resource, Default)]
+    struct Ticks(u32);
+
+    /// turn and thrust at the same time, then let go and drift
+    fn scripted_input_system(
+        mut ticks: ResMut<Ticks>,
+        time: Res<Time<Fixed>>,
+        mut query: Query<&mut ShipInput>,
+    ) {
+        // the timestep is rounded to whole nanoseconds, compare tick centres so that does not add
+        // an extra tick of input
+        let timestep = time.timestep().as_secs_f64();
+        let held = (ticks.0 as f64 + 0.5) * timestep < FLIGHT_SECONDS / 2.0;
+        ticks.0 += 1;
+        for mut input in &mut query {
+            input.movement_factor = if held { 1.0 } else { 0.0 };
+            input.rotation_factor = if held { 0.5 } else { 0.0 };
+        }
+    }
+
+    /// fly the scout with the fixed schedule and physics stepping at `hz`, returns where it ended
+    /// up and its heading
+    fn fly(hz: f64) -> (Vec2, f32) {
+        let timestep = Duration::from_secs_f64(1.0 / hz);
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins.build().disable::<ScheduleRunnerPlugin>(),
+            TransformPlugin,
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(PIXELS_PER_METER)
+                .in_fixed_schedule(),
+        ))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+        .insert_resource(Time::<Fixed>::from_duration(timestep))
+        .init_resource::<Ticks>()
+        .add_systems(Startup, sync_physics_timestep)
+        .add_systems(
+            FixedUpdate,
+            (scripted_input_system, apply_ship_thrust_system)
+                .chain()
+                .before(PhysicsSet::SyncBackend),
+        );
+
+        let ships = ShipDefinitions::load(SHIP_DEFINITIONS_PATH);
+        let scout = ships.get("scout").unwrap();
+        let hull: Vec<Vec2> = scout.hull.iter().map(|&(x, y)| Vec2::new(x, y)).collect();
+        let ship = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                Thrusters {
+                    thrust: scout.thrust,
+                    torque: scout.torque,
+                },
+                ShipInput::default(),
+                RigidBody::Dynamic,
+                Collider::convex_hull(&hull).unwrap(),
+                ColliderMassProperties::Mass(scout.mass),
+                Damping {
+                    linear_damping: scout.linear_damping,
+                    angular_damping: scout.angular_damping,
+                },
+                ExternalForce::default(),
+                Velocity::default(),
+            ))
+            .id();
+
+        app.finish();
+        app.cleanup();
+        let ticks = (FLIGHT_SECONDS * hz).round() as u32;
+        while app.world.resource::<Ticks>().0 < ticks {
+            app.update();
+        }
+
+        let transform = app.world.get::<Transform>(ship).unwrap();
+        let (_, _, heading) = transform.rotation.to_euler(EulerRot::XYZ);
+        (transform.translation.xy(), heading)
+    }
+
+    #[test]
+    fn trajectory_does_not_depend_on_the_tick_rate() {
+        let (reference, reference_heading) = fly(60.0);
+        // the ship has to get somewhere for the comparison to mean anything
+        assert!(reference.length() > 100.0, "ship only reached {reference}");
+        assert!(reference_heading.abs() > 0.5);
+
+        for hz in [30.0, 120.0] {
+            let (position, heading) = fly(hz);
+            let error = position.distance(reference) / reference.length();
+            assert!(
+                error < 0.02,
+                "at {hz} Hz the ship reached {position}, at 60 Hz {reference}"
+            );
+            assert!(
+                (heading - reference_heading).abs() < 0.02,
+                "at {hz} Hz the heading is {heading}, at 60 Hz {reference_heading}"
+            );
+        }
+    }
+}
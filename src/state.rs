@@ -0,0 +1,184 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::difficulty::Difficulty;
+use crate::fog::{ExploredMask, FogOfWar};
+use crate::level::{LevelDefinition, LevelMap};
+use crate::navigation::NavGrid;
+use crate::objectives::Objectives;
+use crate::player::{Lives, Player};
+use crate::race::RaceProgress;
+use crate::time_trial::TimeTrial;
+
+/// top level state of the game
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    /// the level is being built, nothing is simulated yet
+    Loading,
+    InGame,
+    /// the level is kept alive but neither gameplay nor physics advance
+    Paused,
+    /// the player ship was destroyed, the level stays visible behind the game over screen
+    GameOver,
+    /// the objectives of the level, the time trial or the race were completed
+    LevelComplete,
+}
+
+/// what kind of game is played, picked in the main menu
+#[derive(Resource, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    /// fight through the enemies of the level
+    #[default]
+    Campaign,
+    /// fly to the finish of the level as fast as possible against the ghost of the best run,
+    /// without enemies
+    TimeTrial,
+    /// fly laps through the checkpoints of the level, without enemies
+    Race,
+}
+
+/// all gameplay systems, they only run while the game is in [`GameState::InGame`]. Add systems to
+/// this set in both the `Update` and `FixedUpdate` schedules.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameplaySet;
+
+pub struct GameStatePlugin;
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .init_resource::<GameMode>()
+            .configure_sets(Update, GameplaySet.run_if(in_state(GameState::InGame)))
+            .configure_sets(FixedUpdate, GameplaySet.run_if(in_state(GameState::InGame)))
+            .add_systems(OnEnter(GameState::MainMenu), despawn_level)
+            .add_systems(OnEnter(GameState::Loading), despawn_level)
+            .add_systems(OnEnter(GameState::Paused), pause_physics)
+            .add_systems(OnExit(GameState::Paused), resume_physics)
+            .add_systems(
+                Update,
+                (
+                    main_menu_input_system.run_if(in_state(GameState::MainMenu)),
+                    in_game_input_system.run_if(in_state(GameState::InGame)),
+                    player_destroyed_system.run_if(in_state(GameState::InGame)),
+                    paused_input_system.run_if(in_state(GameState::Paused)),
+                    game_over_input_system.run_if(
+                        in_state(GameState::GameOver).or_else(in_state(GameState::LevelComplete)),
+                    ),
+                ),
+            );
+    }
+}
+
+fn main_menu_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut mode: ResMut<GameMode>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Key1) {
+        *difficulty = Difficulty::Easy;
+    } else if keyboard_input.just_pressed(KeyCode::Key2) {
+        *difficulty = Difficulty::Normal;
+    } else if keyboard_input.just_pressed(KeyCode::Key3) {
+        *difficulty = Difficulty::Hard;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        *mode = GameMode::Campaign;
+        next_state.set(GameState::Loading);
+    } else if keyboard_input.just_pressed(KeyCode::T) {
+        *mode = GameMode::TimeTrial;
+        next_state.set(GameState::Loading);
+    } else if keyboard_input.just_pressed(KeyCode::R) {
+        *mode = GameMode::Race;
+        next_state.set(GameState::Loading);
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        exit.send(AppExit);
+    }
+}
+
+/// start a game right away, for runs that are not started from the menu
+pub(crate) fn skip_main_menu(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Loading);
+}
+
+fn in_game_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::P]) {
+        next_state.set(GameState::Paused);
+    }
+}
+
+/// end the game once the last player ship is gone, earlier ones are replaced by the player plugin
+fn player_destroyed_system(
+    player_query: Query<(), With<Player>>,
+    lives: Res<Lives>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if player_query.is_empty() && lives.0 <= 1 {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+fn paused_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::P]) {
+        next_state.set(GameState::InGame);
+    } else if keyboard_input.just_pressed(KeyCode::Q) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn game_over_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        next_state.set(GameState::Loading);
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn pause_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = false;
+}
+
+fn resume_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = true;
+}
+
+/// tear down the current level. Everything living in world space belongs to the level, so all
+/// root entities with a transform except cameras and UI are removed.
+fn despawn_level(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        (
+            With<Transform>,
+            Without<Parent>,
+            Without<Camera>,
+            Without<Node>,
+        ),
+    >,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<LevelDefinition>();
+    commands.remove_resource::<LevelMap>();
+    commands.remove_resource::<NavGrid>();
+    commands.remove_resource::<TimeTrial>();
+    commands.remove_resource::<RaceProgress>();
+    commands.remove_resource::<Objectives>();
+    commands.remove_resource::<FogOfWar>();
+    commands.remove_resource::<ExploredMask>();
+}
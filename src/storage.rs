@@ -0,0 +1,46 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// write a value as ron, creating missing directories on the way. Saved files are a convenience,
+/// so callers get an error message to log instead of a panic.
+pub fn write_ron<T: Serialize>(path: impl AsRef<Path>, value: &T) -> Result<(), String> {
+    let path = path.as_ref();
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+    }
+    let source = ron::to_string(value).map_err(|err| err.to_string())?;
+    std::fs::write(path, source).map_err(|err| err.to_string())
+}
+
+/// read a value written by [`write_ron`], `None` if the file does not exist
+pub fn read_ron<T: DeserializeOwned>(path: impl AsRef<Path>) -> Option<Result<T, String>> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => return Some(Err(err.to_string())),
+    };
+    Some(ron::from_str(&source).map_err(|err| err.to_string()))
+}
+
+/// directory for files that belong to the player rather than the game, like high scores. It is
+/// the platform's data directory (`$XDG_DATA_HOME` or `~/.local/share`, `%APPDATA%`,
+/// `~/Library/Application Support`) with a folder named after the game, or the working directory
+/// if none is set.
+pub fn user_data_dir() -> PathBuf {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".local/share")))
+    };
+    base.unwrap_or_else(|| PathBuf::from("."))
+        .join(env!("CARGO_PKG_NAME"))
+}
@@ -0,0 +1,246 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::level::LevelDefinition;
+use crate::loading::CurrentLevel;
+use crate::player::Player;
+use crate::replay::{Replay, ReplayRecorder};
+use crate::ship::ShipDefinitions;
+use crate::state::{GameMode, GameState, GameplaySet};
+use crate::storage::{read_ron, user_data_dir, write_ron};
+use crate::ticks_to_seconds;
+
+/// opacity of the ghost ship
+const GHOST_ALPHA: f32 = 0.35;
+
+pub struct TimeTrialPlugin;
+impl Plugin for TimeTrialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                from: GameState::Loading,
+                to: GameState::InGame,
+            },
+            start_time_trial.run_if(resource_equals(GameMode::TimeTrial)),
+        )
+        .add_systems(
+            FixedUpdate,
+            (time_trial_system, ghost_tick_system)
+                .after(PhysicsSet::Writeback)
+                .run_if(resource_exists::<TimeTrial>())
+                .in_set(GameplaySet),
+        )
+        .add_systems(Update, ghost_interpolation_system.in_set(GameplaySet));
+    }
+}
+
+/// position and rotation angle of the player ship after a tick
+type GhostSample = (f32, f32, f32);
+
+/// the best time trial run of a level: the replay to reproduce it and the path of the ship to
+/// show as a ghost
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GhostRun {
+    /// ticks from the start to the finish
+    pub ticks: u32,
+    pub replay: Replay,
+    /// transform of the player ship after every tick
+    samples: Vec<GhostSample>,
+}
+
+/// the time trial being flown
+#[derive(Resource)]
+pub struct TimeTrial {
+    /// ticks since the start
+    pub ticks: u32,
+    /// time of the best run so far
+    pub best: Option<u32>,
+    samples: Vec<GhostSample>,
+    finished: bool,
+}
+
+/// a translucent ship following the best run
+#[derive(Component)]
+struct GhostShip {
+    samples: Vec<GhostSample>,
+    /// ticks played so far
+    tick: usize,
+}
+
+/// the goal of a time trial
+#[derive(Component)]
+pub(crate) struct FinishMarker;
+
+/// file with the best run of a level, one per level definition
+fn ghost_path(level: &CurrentLevel) -> PathBuf {
+    user_data_dir()
+        .join("ghosts")
+        .join(format!("{}.ron", level.name()))
+}
+
+/// start the clock, place the finish and let the ghost of the best run fly along
+fn start_time_trial(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ships: Res<ShipDefinitions>,
+    level: Res<LevelDefinition>,
+    current_level: Res<CurrentLevel>,
+) {
+    let path = ghost_path(&current_level);
+    let best = match read_ron::<GhostRun>(&path) {
+        Some(Ok(ghost)) => Some(ghost),
+        Some(Err(err)) => {
+            warn!("could not read ghost {}: {err}", path.display());
+            None
+        }
+        None => None,
+    };
+
+    if let Some(finish) = level.finish {
+        let (x, y) = finish.position;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.2, 1.0, 0.4, 0.3),
+                    custom_size: Some(Vec2::splat(finish.radius * 2.0)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, -0.1),
+                ..default()
+            },
+            FinishMarker,
+        ));
+    } else {
+        warn!(
+            "level {} has no finish, the time trial never ends",
+            current_level.0
+        );
+    }
+
+    if let (Some(ghost), Some(ship)) = (&best, ships.get(&level.player_ship)) {
+        let (r, g, b) = ship.sprite.color;
+        let (x, y) = level.player_start;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(r, g, b, GHOST_ALPHA),
+                    custom_size: Some(Vec2::new(ship.sprite.size.0, ship.sprite.size.1)),
+                    ..default()
+                },
+                texture: ship
+                    .sprite
+                    .texture
+                    .as_ref()
+                    .map(|path| asset_server.load(path.clone()))
+                    .unwrap_or_default(),
+                // below the player ship
+                transform: Transform::from_xyz(x, y, -0.05),
+                ..default()
+            },
+            GhostShip {
+                samples: ghost.samples.clone(),
+                tick: 0,
+            },
+        ));
+    }
+
+    commands.insert_resource(TimeTrial {
+        ticks: 0,
+        best: best.map(|ghost| ghost.ticks),
+        samples: Vec::new(),
+        finished: false,
+    });
+}
+
+/// run the clock, sample the player ship for the ghost of this run and finish the run once the
+/// player reaches the finish. A new best run replaces the stored ghost.
+fn time_trial_system(
+    mut time_trial: ResMut<TimeTrial>,
+    level: Res<LevelDefinition>,
+    current_level: Res<CurrentLevel>,
+    recorder: Res<ReplayRecorder>,
+    player_query: Query<&Transform, With<Player>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(transform) = player_query.get_single() else {
+        return;
+    };
+    if time_trial.finished {
+        return;
+    }
+    let position = transform.translation.xy();
+    let (_, _, angle) = transform.rotation.to_euler(EulerRot::XYZ);
+    time_trial.ticks += 1;
+    time_trial.samples.push((position.x, position.y, angle));
+
+    let Some(finish) = level.finish else {
+        return;
+    };
+    if position.distance(Vec2::from(finish.position)) > finish.radius {
+        return;
+    }
+    time_trial.finished = true;
+    let ticks = time_trial.ticks;
+    info!("time trial finished in {:.2} s", ticks_to_seconds(ticks));
+    next_state.set(GameState::LevelComplete);
+
+    if time_trial.best.is_some_and(|best| best <= ticks) {
+        return;
+    }
+    if let Some(best) = time_trial.best {
+        info!(
+            "new best time, {:.2} s faster",
+            ticks_to_seconds(best - ticks)
+        );
+    }
+    let Some(replay) = recorder.current() else {
+        return;
+    };
+    let ghost = GhostRun {
+        ticks,
+        replay: replay.clone(),
+        samples: std::mem::take(&mut time_trial.samples),
+    };
+    let path = ghost_path(&current_level);
+    if let Err(err) = write_ron(&path, &ghost) {
+        warn!("could not save ghost {}: {err}", path.display());
+    }
+    time_trial.best = Some(ticks);
+}
+
+/// move the ghosts on by one tick
+fn ghost_tick_system(mut query: Query<&mut GhostShip>) {
+    for mut ghost in &mut query {
+        if ghost.tick < ghost.samples.len() {
+            ghost.tick += 1;
+        }
+    }
+}
+
+/// place the ghosts between their last and next sample by the time already spent on the next
+/// tick, so they move smoothly at any frame rate. A ghost that reached its finish stays there.
+fn ghost_interpolation_system(
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<(&GhostShip, &mut Transform)>,
+) {
+    let overstep = fixed_time.overstep_percentage();
+    for (ghost, mut transform) in &mut query {
+        let Some(&(x, y, angle)) = ghost.tick.checked_sub(1).and_then(|i| ghost.samples.get(i))
+        else {
+            continue;
+        };
+        let (next_x, next_y, next_angle) = ghost
+            .samples
+            .get(ghost.tick)
+            .copied()
+            .unwrap_or((x, y, angle));
+        let from = Vec2::new(x, y);
+        let to = Vec2::new(next_x, next_y);
+        transform.translation = from.lerp(to, overstep).extend(transform.translation.z);
+        transform.rotation =
+            Quat::from_rotation_z(angle).slerp(Quat::from_rotation_z(next_angle), overstep);
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::health::{apply_damage_system, DamageEvent, Team};
+use crate::level::{carve_terrain_system, CarveTerrain, Terrain};
+use crate::particles::{ParticleEffect, ParticlePreset};
+use crate::ship::{Loadout, ShipInput};
+use crate::state::GameplaySet;
+
+/// file containing all weapon definitions, keyed by name
+const WEAPON_DEFINITIONS_PATH: &str = "assets/weapons.ron";
+
+pub struct WeaponPlugin;
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WeaponDefinitions::load(WEAPON_DEFINITIONS_PATH))
+            .add_systems(
+                FixedUpdate,
+                (
+                    ship_weapon_system,
+                    // hits are read in the same tick, events only live for two frames and
+                    // there may be no tick in between when frames are shorter than ticks
+                    projectile_system
+                        .before(apply_damage_system)
+                        .before(carve_terrain_system),
+                )
+                    .chain()
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// all weapons known to the game, loaded from [`WEAPON_DEFINITIONS_PATH`]
+#[derive(Resource, Deserialize, Default)]
+#[serde(transparent)]
+pub struct WeaponDefinitions(HashMap<String, WeaponDefinition>);
+
+impl WeaponDefinitions {
+    /// read and parse a ron file with weapon definitions, panics like the ship definitions do
+    pub fn load(path: &str) -> Self {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("could not read weapon definitions {path}: {err}"));
+        ron::from_str(&source)
+            .unwrap_or_else(|err| panic!("could not parse weapon definitions {path}: {err}"))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WeaponDefinition> {
+        self.0.get(name)
+    }
+}
+
+/// stats of a projectile weapon
+#[derive(Deserialize, Clone, Debug)]
+pub struct WeaponDefinition {
+    /// muzzle speed of the projectiles in pixels per second
+    pub projectile_speed: f32,
+    pub damage: f32,
+    /// seconds between two shots
+    pub cooldown: f32,
+    /// rounds a ship carries into a run, unlimited when left out
+    #[serde(default)]
+    pub ammo: Option<u32>,
+    /// seconds until a projectile that hit nothing disappears
+    pub lifetime: f32,
+    /// radius of the hole a projectile blasts into the terrain, 0.0 leaves the terrain intact
+    #[serde(default)]
+    pub carve_radius: f32,
+    /// projectile sprite size in pixels
+    pub size: f32,
+    pub color: (f32, f32, f32),
+}
+
+/// a flying projectile, moved by [`projectile_system`]
+#[derive(Component)]
+pub struct Projectile {
+    pub velocity: Vec2,
+    pub damage: f32,
+    pub carve_radius: f32,
+    /// seconds left until the projectile disappears
+    pub lifetime: f32,
+    /// entity that fired the projectile, it can not hit itself
+    pub owner: Entity,
+    pub team: Team,
+}
+
+/// spawn a projectile of the given weapon flying in `direction`, on top of `base_velocity`
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    weapon: &WeaponDefinition,
+    owner: Entity,
+    team: Team,
+    position: Vec2,
+    direction: Vec2,
+    base_velocity: Vec2,
+) -> Entity {
+    let (r, g, b) = weapon.color;
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(r, g, b),
+                    custom_size: Some(Vec2::splat(weapon.size)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(1.0)),
+                ..default()
+            },
+            Projectile {
+                velocity: base_velocity + direction.normalize_or_zero() * weapon.projectile_speed,
+                damage: weapon.damage,
+                carve_radius: weapon.carve_radius,
+                lifetime: weapon.lifetime,
+                owner,
+                team,
+            },
+        ))
+        .id()
+}
+
+/// fire the first weapon of every ship whose pilot pulls the trigger
+fn ship_weapon_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    weapons: Res<WeaponDefinitions>,
+    mut query: Query<(
+        Entity,
+        &ShipInput,
+        &mut Loadout,
+        &Transform,
+        &Sprite,
+        Option<&Velocity>,
+        Option<&Team>,
+    )>,
+) {
+    for (entity, input, mut loadout, transform, sprite, velocity, team) in &mut query {
+        // only touch cooling weapons, the HUD reacts to changes of the loadout
+        if loadout.cooldowns.iter().any(|&cooldown| cooldown > 0.0) {
+            for cooldown in loadout.cooldowns.iter_mut() {
+                *cooldown = (*cooldown - time.delta_seconds()).max(0.0);
+            }
+        }
+        let ready = loadout
+            .cooldowns
+            .first()
+            .is_some_and(|&cooldown| cooldown <= 0.0);
+        if !input.fire || !ready {
+            continue;
+        }
+        let Some(weapon) = weapons.get(&loadout.weapons[0]) else {
+            warn!("unknown weapon {}", loadout.weapons[0]);
+            continue;
+        };
+        if loadout.ammo_left(0, weapon) == Some(0) {
+            continue;
+        }
+        loadout.cooldowns[0] = weapon.cooldown;
+        loadout.rounds_fired[0] += 1;
+
+        // projectiles leave at the nose of the ship
+        let forward = (transform.rotation * Vec3::Y).xy();
+        let nose_offset = sprite.custom_size.map_or(0.0, |size| size.y / 2.0) + weapon.size;
+        spawn_projectile(
+            &mut commands,
+            weapon,
+            entity,
+            team.copied().unwrap_or(Team::Player),
+            transform.translation.xy() + forward * nose_offset,
+            forward,
+            velocity.map_or(Vec2::ZERO, |velocity| velocity.linvel),
+        );
+    }
+}
+
+/// what a projectile hit causes
+#[derive(SystemParam)]
+struct HitEvents<'w> {
+    damage: EventWriter<'w, DamageEvent>,
+    carve: EventWriter<'w, CarveTerrain>,
+    effects: EventWriter<'w, ParticleEffect>,
+}
+
+/// move projectiles and resolve their hits. The path of every step is ray cast so fast
+/// projectiles can not tunnel through thin terrain or small ships.
+fn projectile_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    context: Res<RapierContext>,
+    terrain: Query<(), With<Terrain>>,
+    mut query: Query<(Entity, &mut Projectile, &mut Transform)>,
+    mut hits: HitEvents,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut projectile, mut transform) in &mut query {
+        projectile.lifetime -= delta;
+        if projectile.lifetime <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let position = transform.translation.xy();
+        let step = projectile.velocity * delta;
+        let distance = step.length();
+        let filter = QueryFilter::new()
+            .exclude_sensors()
+            .exclude_collider(projectile.owner);
+
+        let hit = (distance > f32::EPSILON)
+            .then(|| context.cast_ray(position, step / distance, distance, true, filter))
+            .flatten();
+        let Some((hit_entity, hit_distance)) = hit else {
+            transform.translation.x += step.x;
+            transform.translation.y += step.y;
+            continue;
+        };
+
+        let hit_position = position + step / distance * hit_distance;
+        commands.entity(entity).despawn();
+        hits.effects.send(ParticleEffect {
+            preset: ParticlePreset::BulletImpact,
+            position: hit_position,
+            direction: -step,
+            velocity: Vec2::ZERO,
+        });
+
+        if terrain.contains(hit_entity) {
+            if projectile.carve_radius > 0.0 {
+                hits.carve.send(CarveTerrain {
+                    center: hit_position,
+                    radius: projectile.carve_radius,
+                });
+            }
+        } else {
+            hits.damage.send(DamageEvent {
+                target: hit_entity,
+                amount: projectile.damage,
+                source_team: Some(projectile.team),
+            });
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use laughing_rotary_particle::headless::{
+    headless_app, load_level, simulate, SimulationScript, SimulationSummary,
+};
+use laughing_rotary_particle::state::GameState;
+
+/// ticks simulated, the smoke script thrusts until the last of them
+const STEPS: u32 = 220;
+
+fn simulate_script(source: &str) -> (SimulationSummary, SimulationSummary) {
+    let script: SimulationScript = ron::from_str(source).expect("valid script");
+    let mut app = headless_app(script);
+    load_level(&mut app);
+    let start = simulate(&mut app, 0);
+    (start, simulate(&mut app, STEPS))
+}
+
+#[test]
+fn smoke_script_flies_the_ship() {
+    let source = std::fs::read_to_string("assets/simulations/smoke.ron").unwrap();
+    let (start, end) = simulate_script(&source);
+    let (_, drift) = simulate_script("(steps: 220)");
+
+    assert_eq!(end.state, GameState::InGame);
+    assert_eq!(end.steps, STEPS);
+    assert!(end.player_health > 0.0);
+
+    let start = start.player_position.expect("player ship spawned");
+    let end = end.player_position.expect("player ship survived");
+    let drift = drift.player_position.expect("player ship survived");
+    // without input the ship only drifts, the script turned it and gave it thrust
+    assert!(
+        end.distance(drift) > 50.0,
+        "scripted ship at {end}, drifting ship at {drift}, started at {start}"
+    );
+}